@@ -8,13 +8,71 @@ pub const SUPPLY_POOL_KEY_PREFIX: &[u8] =b"supply_pool_key_prefix";
 pub const VIEWING_KEY_KEY: &[u8] = b"viewingkey";
 pub const STAKING_VK: &str = "sefi_stakepool_VK";
 pub const RESPONSE_BLOCK_SIZE: usize = 256;
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const LOTTERY_KEY: &[u8] = b"lottery_key";
 pub const LOTTERY_KEY_PREFIX: &[u8] = b"lottery_key_prefix";
 
-pub const LOTTERY_ENTRY_KEY: &[u8] = b"lottery_entry_key";
 pub const LAST_LOTTERY_KEY: &[u8] = b"last_lottery_key";
+pub const ROUND_KEY_PREFIX: &[u8] = b"round_key_prefix";
 pub const USER_INFO_KEY:&[u8] = b"user_info_key";
 pub const USER_WINNING_HISTORY_KEY: &[u8] = b"user_winning_history";
+pub const DRAW_COMMITMENT_KEY: &[u8] = b"draw_commitment_key";
+pub const DRAW_COMMITMENT_KEY_PREFIX: &[u8] = b"draw_commitment_key_prefix";
+pub const LAPSED_COMMITTERS_KEY: &[u8] = b"lapsed_committers_key";
+pub const PENDING_CONFIG_CHANGES_KEY: &[u8] = b"pending_config_changes_key";
+pub const PENDING_CONFIG_CHANGES_KEY_PREFIX: &[u8] = b"pending_config_changes_key_prefix";
+pub const ROLES_KEY_PREFIX: &[u8] = b"roles_key_prefix";
+pub const PENDING_HANDOVER_KEY: &[u8] = b"pending_handover_key";
+pub const PENDING_HANDOVER_KEY_PREFIX: &[u8] = b"pending_handover_key_prefix";
+pub const ACTION_QUEUE_KEY: &[u8] = b"action_queue_key";
+pub const ACTION_QUEUE_KEY_PREFIX: &[u8] = b"action_queue_key_prefix";
+pub const PROPOSALS_KEY: &[u8] = b"proposals_key";
+pub const PROPOSALS_KEY_PREFIX: &[u8] = b"proposals_key_prefix";
+pub const MIGRATION_HISTORY_KEY: &[u8] = b"migration_history_key";
+pub const USER_TOTALS_KEY: &[u8] = b"user_totals_key";
+pub const USER_TOTALS_KEY_PREFIX: &[u8] = b"user_totals_key_prefix";
+pub const SLOT_REGISTRY_KEY: &[u8] = b"slot_registry_key";
+pub const SLOT_REGISTRY_KEY_PREFIX: &[u8] = b"slot_registry_key_prefix";
+pub const ENTRY_TREE_KEY_PREFIX: &[u8] = b"entry_tree_key_prefix";
+pub const DEFICIT_TREE_KEY_PREFIX: &[u8] = b"deficit_tree_key_prefix";
+pub const SQRT_ENTRY_TREE_KEY_PREFIX: &[u8] = b"sqrt_entry_tree_key_prefix";
+pub const SQRT_DEFICIT_TREE_KEY_PREFIX: &[u8] = b"sqrt_deficit_tree_key_prefix";
+pub const DEFICIT_SLOTS_KEY_PREFIX: &[u8] = b"deficit_slots_key_prefix";
+pub const USER_SLOT_KEY_PREFIX: &[u8] = b"user_slot_key_prefix";
+pub const SLOT_OWNER_KEY_PREFIX: &[u8] = b"slot_owner_key_prefix";
+
+//Prize tiers are percentages of the prize, one distinct winner is drawn per tier
+pub const DEFAULT_PRIZE_TIERS: [u64; 1] = [100];
+pub const MAX_PRIZE_TIERS: usize = 10;
+
+//Blocks between CommitDraw and the earliest RevealDraw, and how long a commitment stays valid after that
+pub const DEFAULT_DRAW_REVEAL_DELAY: u64 = 2;
+pub const DRAW_REVEAL_WINDOW: u64 = 100;
+
+pub const DEFAULT_ROUND_DURATION: u64 = 86400;
+
+//Fee limits in basis points of the prize
+pub const MAX_TRIGGERER_FEE_BPS: u64 = 1000;
+pub const MAX_PROTOCOL_FEE_BPS: u64 = 2000;
+pub const MAX_CHARITY_FEE_BPS: u64 = 2000;
+pub const MAX_TOTAL_FEE_BPS: u64 = 3000;
+
+//Seconds between queueing a timelocked action and the earliest time it can be executed
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 86400;
+pub const MAX_QUEUED_ACTIONS: usize = 10;
+
+//Addresses a single role can be granted to, and signers of the multisig
+pub const MAX_ROLE_MEMBERS: usize = 10;
+pub const MAX_OPEN_PROPOSALS: usize = 10;
+
+//1 sefi
+pub const DEFAULT_MIN_DEPOSIT: u128 = 1000000;
+
+//Unbonding records a user can have waiting at once
+pub const MAX_PENDING_UNBONDINGS: usize = 16;
+
+//Square-root weights are kept in fixed point, sqrt(amount * SQRT_WEIGHT_SCALE), so small deposits keep their precision
+pub const SQRT_WEIGHT_SCALE: u128 = 1_000_000;
 
 
 
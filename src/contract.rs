@@ -1,28 +1,26 @@
 //Crate import
 use crate::constants::*;
 use crate::viewing_keys::{ViewingKey, VIEWING_KEY_SIZE};
-use crate::state::{SupplyPool, UserInfo, Config, Lottery, LastLotteryResults, SecretContract, UserWinningHistory, LotteryEntries};
-use crate::msg::{HandleAnswer, HandleMsg, InitMsg, LPStakingRewardsResponse, QueryAnswer, QueryMsg, LPStakingQueryMsg, LPStakingHandleMsg, ResponseStatus::Success, ResponseStatus::Failure};
+use crate::state::{SupplyPool, UserInfo, Config, Lottery, LastLotteryResults, SecretContract, UserWinningHistory, DrawCommitment, RoundRecord, RoundStatus, SlotRegistry, RoundEntry, WeightCurve, UnbondingRecord, PendingConfigChanges, FeeSchedule, Role, PendingHandover, TimelockedAction, QueuedAction, ActionQueue, Multisig, AdminAction, Proposal, ProposalBook, ContractStatus, StakingMigration, UserTotals, LapsedCommitters, EarlyExitFee, DepositLimits, PermissionlessDraws};
+use crate::utils::{ct_slice_compare, isqrt};
+use crate::fenwick;
+use crate::msg::{HandleAnswer, HandleMsg, InitMsg, LPStakingRewardsResponse, LPStakingBalanceResponse, QueryAnswer, QueryMsg, LPStakingQueryMsg, LPStakingHandleMsg, ResponseStatus::Success};
 
 //Cosmwasm import
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
-use cosmwasm_std::{Api, Binary, CosmosMsg, Env, Extern, HandleResponse, InitResponse, Querier, ReadonlyStorage, StdError, StdResult, Storage, Uint128, WasmMsg, from_binary, to_binary};
+use cosmwasm_std::{Api, Binary, CosmosMsg, Env, Extern, HandleResponse, InitResponse, Querier, ReadonlyStorage, StdError, StdResult, Storage, Uint128, WasmMsg, from_binary, to_binary, log};
 use cosmwasm_std::HumanAddr;
 //secret toolkit import
 use secret_toolkit::storage::{TypedStore, AppendStore, AppendStoreMut};
 use secret_toolkit::snip20::{transfer_msg, send_msg};
 use secret_toolkit::utils::{Query, pad_handle_result, pad_query_result};
 use secret_toolkit::{crypto::sha_256, storage::TypedStoreMut, snip20};
-use secret_toolkit::incubator::{GenerationalStore, GenerationalStoreMut};
-use secret_toolkit::incubator::generational_store::Entry;
 
 //Rust functions
 use rand::prelude::*;
 use sha2::{Digest, Sha256};
 use rand_core::SeedableRng;
 use rand_chacha::ChaChaRng;
-use rand::distributions::WeightedIndex;
-use std::borrow::Borrow;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
@@ -44,25 +42,73 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     } else {
         triggerer = env.message.sender.clone();
     }
+
+    let fees = FeeSchedule {
+        triggerer_fee_bps: msg.triggerer_share_percentage,
+        triggerer_fee_recipient: msg.triggerer_fee_recipient.clone().unwrap_or(triggerer.clone()),
+        protocol_fee_bps: msg.protocol_fee_bps.unwrap_or(0),
+        treasury: msg.treasury.clone().unwrap_or(admin.clone()),
+        charity_fee_bps: msg.charity_fee_bps.unwrap_or(0),
+        charity: msg.charity.clone(),
+    };
+    validate_fee_schedule(&fees)?;
+
+    let prize_tiers = msg.prize_tiers.clone().unwrap_or(DEFAULT_PRIZE_TIERS.to_vec());
+    validate_prize_tiers(&prize_tiers)?;
+
+    let draw_reveal_delay = msg.draw_reveal_delay.unwrap_or(DEFAULT_DRAW_REVEAL_DELAY);
+    if draw_reveal_delay == 0 {
+        return Err(StdError::generic_err("Draw reveal delay must be at least one block"));
+    }
+    if msg.ticket_size == Some(Uint128(0)) {
+        return Err(StdError::generic_err("Ticket size must be greater than zero"));
+    }
+    let weight_curve = msg.weight_curve.clone().unwrap_or(WeightCurve::Linear);
+    validate_weight_curve(&weight_curve)?;
+    let early_exit_fee_percentage = msg.early_exit_fee_percentage.unwrap_or(0);
+    validate_early_exit_fee(early_exit_fee_percentage)?;
+    let min_deposit = msg.min_deposit.unwrap_or(Uint128(DEFAULT_MIN_DEPOSIT));
+    validate_deposit_limits(min_deposit, msg.max_deposit_per_user)?;
+    validate_multisig(&msg.multisig)?;
+
     let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
     let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>, _>::attach(&mut config_prefixed);
 
     configstore.store(
         CONFIG_KEY,
         &Config {
-            admin,
-            triggerer,
-            triggerer_share_percentage: msg.triggerer_share_percentage,
+            fees,
+            prize_tiers,
             token: msg.token.clone(),
             staking_contract: msg.staking_contract.clone(),
             viewing_key: msg.viewing_key.clone(),
             prng_seed: prng_seed_hashed.to_vec(),
-            is_stopped: false,
-            is_stopped_can_withdraw: false,
+            draw_reveal_delay,
+            permissionless_draws: false,
+            max_triggerer_bounty: Uint128(0),
+            ticket_size: msg.ticket_size,
+            weight_curve: weight_curve.clone(),
+            unbonding_period: msg.unbonding_period.unwrap_or(0),
+            early_exit_fee_percentage,
+            early_exit_window: msg.early_exit_window.unwrap_or(0),
+            min_deposit,
+            max_deposit_per_user: msg.max_deposit_per_user,
+            pool_cap: msg.pool_cap,
+            timelock_delay: msg.timelock_delay.unwrap_or(DEFAULT_TIMELOCK_DELAY),
+            multisig: msg.multisig.clone(),
+            status: ContractStatus::Normal,
+            status_reason: None,
             own_addr: env.contract.address,
         },
     )?;
 
+    //The admin starts with every role but the triggerer's
+    store_role_members(&mut deps.storage, Role::Owner, &vec![admin.clone()])?;
+    store_role_members(&mut deps.storage, Role::Pauser, &vec![admin.clone()])?;
+    store_role_members(&mut deps.storage, Role::ParameterManager, &vec![admin.clone()])?;
+    store_role_members(&mut deps.storage, Role::FeeCollector, &vec![admin])?;
+    store_role_members(&mut deps.storage, Role::Triggerer, &vec![triggerer])?;
+
     let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
     let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
 
@@ -73,12 +119,30 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
             total_rewards_restaked: Uint128(0),
             pending_staking_rewards: Uint128(0),
             triggering_cost: Uint128(0),
+            protocol_fees: Uint128(0),
+            charity_fees: Uint128(0),
+            rolled_over_prize: Uint128(0),
+            rolled_over_rounds: 0,
+            early_exit_fees: Uint128(0),
+            total_pending_withdrawals: Uint128(0),
         },
     )?;
 
+    let mut slot_registry_prefixed = PrefixedStorage::multilevel(&[SLOT_REGISTRY_KEY_PREFIX], &mut deps.storage);
+    let mut slot_registry_store = TypedStoreMut::<SlotRegistry, PrefixedStorage<'_, S>>::attach(&mut slot_registry_prefixed);
+    slot_registry_store.store(SLOT_REGISTRY_KEY, &SlotRegistry { next_slot: 1, holders: 0 })?;
+
     //lottery init
     let time = env.block.time;
-    let duration = 86400u64;
+    let duration = msg.duration.unwrap_or(DEFAULT_ROUND_DURATION);
+    if duration == 0 {
+        return Err(StdError::generic_err("Round duration must be greater than zero"));
+    }
+    //The first round runs to the first boundary of the schedule
+    let end_time = match msg.schedule_anchor {
+        Some(anchor) => next_boundary(anchor, duration, time),
+        None => time + duration,
+    };
 
     //Create first lottery
     // Save to state
@@ -89,9 +153,12 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
         &Lottery {
             entropy: prng_seed_hashed.to_vec(),
             start_time: time + 0,
-            end_time: time + duration + 0,
+            end_time,
             seed: prng_seed_hashed.to_vec(),
             duration,
+            round_id: 1,
+            weight_curve,
+            schedule_anchor: msg.schedule_anchor,
         },
     )?;
 
@@ -135,41 +202,16 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
 
-    if config.is_stopped {
-        let response = match msg {
-            //USER->Viewing Key
-            HandleMsg::CreateViewingKey { entropy, .. } => { create_viewing_key(deps, env, entropy) }
-            HandleMsg::SetViewingKey { key, .. } => set_viewing_key(deps, env, key),
-
-            //Admin  ---> ChangeStakingContractFlow
-            // => 1.StopContract 2.EmergencyRedeemFromStaking
-            HandleMsg::EmergencyRedeemFromStaking {} => emergency_redeem_from_staking(deps, env),
-
-            //Option 1)     3. Allow users to Withdraw their amount
-            HandleMsg::AllowWithdrawWhenStopped {} => allow_withdraw_when_stopped(deps, env),
-            HandleMsg::WithdrawExcess {} => withdraw_excess(deps, env),
-
-            //Option 2)     3. Redelegate the contract
-            HandleMsg::ChangeStakingContract { address, contract_hash } => change_staking_contract(deps, env, address, contract_hash),
-            HandleMsg::RedelegateToNewContract {} => redelegate_to_contract(deps, env),
-
-            HandleMsg::ResumeContract {} => resume_contract(deps, env),
-            HandleMsg::TriggeringCostWithdraw {} => triggering_cost_withdraw(deps, env),
-
-            //Allow withdraw
-            HandleMsg::Withdraw { amount } => withdraw(deps, env, amount),
-
-            _ => Err(StdError::generic_err(
-                "This contract is stopped and this action is not allowed",
-            )),
-        };
-        return pad_handle_result(response, RESPONSE_BLOCK_SIZE);
+    if let Err(err) = check_contract_status(config.status, &msg) {
+        return pad_handle_result(Err(err), RESPONSE_BLOCK_SIZE);
     }
+    let invariant_env = env.clone();
 
     let response = match msg {
 
         // Triggerer
-        HandleMsg::ClaimRewards {} => claim_rewards(deps, env),
+        HandleMsg::CommitDraw { entropy_hash } => commit_draw(deps, env, entropy_hash),
+        HandleMsg::RevealDraw { entropy } => reveal_draw(deps, env, entropy),
 
         //USER
         HandleMsg::Receive { from, amount, msg, .. } => receive(deps, env, from, amount, msg),
@@ -180,19 +222,221 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
         HandleMsg::CreateViewingKey { entropy, .. } => { create_viewing_key(deps, env, entropy) }
         HandleMsg::SetViewingKey { key, .. } => set_viewing_key(deps, env, key),
 
+        //Admin  ---> ChangeStakingContractFlow
+        // => 1.SetContractStatus FullyStopped 2.EmergencyRedeemFromStaking
+        HandleMsg::EmergencyRedeemFromStaking {} => {
+            submit_admin_action(deps, env, AdminAction::Queue { action: TimelockedAction::EmergencyRedeemFromStaking {} })
+        }
+
+        //Option 1)     3. Allow users to Withdraw their amount with SetContractStatus WithdrawOnly
+        HandleMsg::WithdrawExcess {} => submit_admin_action(deps, env, AdminAction::Queue { action: TimelockedAction::WithdrawExcess {} }),
+
+        //Option 2)     3. Redelegate the contract
+        HandleMsg::ChangeStakingContract { address, contract_hash } => {
+            submit_admin_action(deps, env, AdminAction::Queue { action: TimelockedAction::ChangeStakingContract { address, contract_hash } })
+        }
+        HandleMsg::RedelegateToNewContract {} => redelegate_to_contract(deps, env),
+        HandleMsg::MigrateStakingContract { new_contract } => {
+            submit_admin_action(deps, env, AdminAction::Queue { action: TimelockedAction::MigrateStakingContract { new_contract } })
+        }
+
         //Admin
-        HandleMsg::ChangeAdmin { admin } => change_admin(deps, env, admin),
-        HandleMsg::ChangeTriggerer { admin } => change_triggerer(deps, env, admin),
-        HandleMsg::ChangeTriggererShare { percentage, .. } => change_triggerer_share(deps, env, percentage),
-        HandleMsg::ChangeLotteryDuration { duration } => change_lottery_duration(deps, env, duration),
-        HandleMsg::TriggeringCostWithdraw {} => triggering_cost_withdraw(deps, env),
-        HandleMsg::StopContract {} => stop_contract(deps, env),
+        HandleMsg::SetContractStatus { status, reason } if is_resume(config.status, status) => {
+            submit_admin_action(deps, env, AdminAction::SetContractStatus { status, reason })
+        }
+        HandleMsg::SetContractStatus { status, reason } => set_contract_status(deps, env, status, reason),
+        HandleMsg::ProposeAdmin { address } => submit_admin_action(deps, env, AdminAction::ProposeAdmin { address }),
+        HandleMsg::AcceptAdmin {} => accept_handover(deps, env, Role::Owner),
+        HandleMsg::CancelAdminProposal {} => cancel_handover(deps, env, Role::Owner),
+        HandleMsg::ProposeTriggerer { address } => submit_admin_action(deps, env, AdminAction::ProposeTriggerer { address }),
+        HandleMsg::AcceptTriggerer {} => accept_handover(deps, env, Role::Triggerer),
+        HandleMsg::CancelTriggererProposal {} => cancel_handover(deps, env, Role::Triggerer),
+        HandleMsg::ExecuteAction { id } => execute_action(deps, env, id),
+        HandleMsg::CancelAction { id } => submit_admin_action(deps, env, AdminAction::CancelAction { id }),
+        HandleMsg::ChangeTimelockDelay { delay } => {
+            submit_admin_action(deps, env, AdminAction::Queue { action: TimelockedAction::ChangeTimelockDelay { delay } })
+        }
+        HandleMsg::Approve { proposal_id } => approve(deps, env, proposal_id),
+        HandleMsg::CancelProposal { proposal_id } => cancel_proposal(deps, env, proposal_id),
+        HandleMsg::SetMultisig { multisig } => submit_admin_action(deps, env, AdminAction::SetMultisig { multisig }),
+        HandleMsg::GrantRole { role, address } => submit_admin_action(deps, env, AdminAction::GrantRole { role, address }),
+        HandleMsg::RevokeRole { role, address } => submit_admin_action(deps, env, AdminAction::RevokeRole { role, address }),
+        HandleMsg::ChangeTriggererShare { percentage, .. } => submit_admin_action(deps, env, AdminAction::ChangeTriggererShare { percentage }),
+        HandleMsg::ChangePrizeTiers { tiers } => submit_admin_action(deps, env, AdminAction::ChangePrizeTiers { tiers }),
+        HandleMsg::ChangeFeeSchedule { fees } => submit_admin_action(deps, env, AdminAction::ChangeFeeSchedule { fees }),
+        HandleMsg::ChangePermissionlessDraws { enabled, max_bounty } => {
+            submit_admin_action(deps, env, AdminAction::ChangePermissionlessDraws { enabled, max_bounty })
+        }
+        HandleMsg::ChangeWeightCurve { curve } => submit_admin_action(deps, env, AdminAction::ChangeWeightCurve { curve }),
+        HandleMsg::ChangeUnbondingPeriod { period } => submit_admin_action(deps, env, AdminAction::ChangeUnbondingPeriod { period }),
+        HandleMsg::ChangeEarlyExitFee { percentage, window } => {
+            submit_admin_action(deps, env, AdminAction::ChangeEarlyExitFee { percentage, window })
+        }
+        HandleMsg::ChangeDepositLimits { min_deposit, max_deposit_per_user, pool_cap } => {
+            submit_admin_action(deps, env, AdminAction::ChangeDepositLimits { min_deposit, max_deposit_per_user, pool_cap })
+        }
+        HandleMsg::ChangeLotteryDuration { duration } => submit_admin_action(deps, env, AdminAction::ChangeLotteryDuration { duration }),
+        HandleMsg::TriggeringCostWithdraw {} => withdraw_fees(deps, env),
+        HandleMsg::WithdrawFees {} => withdraw_fees(deps, env),
 
         _ => Err(StdError::generic_err("Unavailable or unknown handle message")),
     };
+    let response = response.and_then(|response| check_invariants(deps, &invariant_env, response));
     pad_handle_result(response, RESPONSE_BLOCK_SIZE)
 }
 
+//Runs after every successful handle. On a mismatch the contract switches itself to WithdrawOnly and redeems
+//everything it staked, so users can still take out what they are owed while the accounting is investigated.
+fn check_invariants<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    mut response: HandleResponse,
+) -> StdResult<HandleResponse> {
+    let totals = load_user_totals(&deps.storage)?;
+    let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
+    let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
+    let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+
+    let mut failures: Vec<String> = vec![];
+    if totals.principal != supply_pool.total_tokens_staked {
+        failures.push(format!(
+            "users' principal {} does not match total tokens staked {}", totals.principal, supply_pool.total_tokens_staked
+        ));
+    }
+    if totals.pending_withdrawals != supply_pool.total_pending_withdrawals {
+        failures.push(format!(
+            "users' pending withdrawals {} do not match total pending withdrawals {}", totals.pending_withdrawals, supply_pool.total_pending_withdrawals
+        ));
+    }
+    if failures.is_empty() {
+        return Ok(response);
+    }
+
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let mut config: Config = configstore.load(CONFIG_KEY)?;
+    //An admin stop is at least as strict, it is left as it is
+    if is_stopped(config.status) {
+        response.log.push(log("invariant_failed", format!("Accounting invariant failed: {}", failures.join("; "))));
+        return Ok(response);
+    }
+
+    //WithdrawOnly pays delegated tokens out of this contract's own balance, so the stake has to come back with the stop.
+    //What the staking contract actually holds is redeemed, the totals are what can not be trusted.
+    //A staking contract that can not be queried must not keep the contract from stopping, the redeem is then left to the owner.
+    let staking = query_staked_balance(&deps, &config)
+        .and_then(|staked_balance| Ok((staked_balance, query_pending_rewards(&deps, env, &config)?)));
+    if let Err(err) = &staking {
+        failures.push(format!("staking contract query failed: {}", err));
+    }
+    let reason = format!("Accounting invariant failed: {}", failures.join("; "));
+    response.log.push(log("invariant_failed", reason.clone()));
+    config.status = ContractStatus::WithdrawOnly;
+    config.status_reason = Some(reason);
+    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
+    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
+    configstore.store(CONFIG_KEY, &config)?;
+
+    let (staked_balance, staking_rewards_response) = match staking {
+        Ok(staking) => staking,
+        Err(_) => return Ok(response),
+    };
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    supply_pool.pending_staking_rewards += staking_rewards_response.rewards.rewards;
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
+    if staked_balance > Uint128(0) {
+        response.messages.push(
+            WasmMsg::Execute {
+                contract_addr: config.staking_contract.address,
+                callback_code_hash: config.staking_contract.contract_hash,
+                msg: to_binary(&LPStakingHandleMsg::Redeem {
+                    amount: staked_balance
+                })?,
+                send: vec![],
+            }
+                .into()
+        );
+    }
+
+    Ok(response)
+}
+
+fn is_stopped(status: ContractStatus) -> bool {
+    match status {
+        ContractStatus::WithdrawOnly | ContractStatus::FullyStopped => true,
+        _ => false,
+    }
+}
+
+//The two paused statuses each allow something the other blocks, so they share a rank
+fn status_rank(status: ContractStatus) -> u8 {
+    match status {
+        ContractStatus::Normal => 0,
+        ContractStatus::DepositsPaused | ContractStatus::DrawsPaused => 1,
+        ContractStatus::WithdrawOnly => 2,
+        ContractStatus::FullyStopped => 3,
+    }
+}
+
+//Any change that is not strictly more restrictive reopens something, which is left to the owner
+fn is_resume(current: ContractStatus, status: ContractStatus) -> bool {
+    status != current && status_rank(status) <= status_rank(current)
+}
+
+//The one place deciding which messages each contract status accepts
+fn check_contract_status(status: ContractStatus, msg: &HandleMsg) -> StdResult<()> {
+    let allowed = match msg {
+        //in every status
+        HandleMsg::CreateViewingKey { .. }
+        | HandleMsg::SetViewingKey { .. }
+        | HandleMsg::SetContractStatus { .. }
+        | HandleMsg::GrantRole { .. }
+        | HandleMsg::RevokeRole { .. }
+        | HandleMsg::ProposeAdmin { .. }
+        | HandleMsg::AcceptAdmin {}
+        | HandleMsg::CancelAdminProposal {}
+        | HandleMsg::ExecuteAction { .. }
+        | HandleMsg::CancelAction { .. }
+        | HandleMsg::ChangeTimelockDelay { .. }
+        | HandleMsg::Approve { .. }
+        | HandleMsg::CancelProposal { .. }
+        | HandleMsg::SetMultisig { .. }
+        | HandleMsg::MigrateStakingContract { .. }
+        | HandleMsg::TriggeringCostWithdraw {}
+        | HandleMsg::WithdrawFees {} => true,
+
+        //ChangeStakingContractFlow, only once the contract is stopped
+        HandleMsg::EmergencyRedeemFromStaking {}
+        | HandleMsg::WithdrawExcess {}
+        | HandleMsg::ChangeStakingContract { .. }
+        | HandleMsg::RedelegateToNewContract {} => {
+            if !is_stopped(status) {
+                return Err(StdError::generic_err("This action is only allowed while the contract is stopped"));
+            }
+            true
+        }
+
+        HandleMsg::Receive { .. } => status == ContractStatus::Normal || status == ContractStatus::DrawsPaused,
+        HandleMsg::CommitDraw { .. } | HandleMsg::RevealDraw { .. } => {
+            status == ContractStatus::Normal || status == ContractStatus::DepositsPaused
+        }
+        //Principal still staked has to be unbonded before it can be withdrawn
+        HandleMsg::Withdraw { .. } | HandleMsg::TriggerWithdraw { .. } => status != ContractStatus::FullyStopped,
+        _ => !is_stopped(status),
+    };
+
+    if allowed {
+        return Ok(());
+    }
+    match status {
+        ContractStatus::DepositsPaused => Err(StdError::generic_err("Deposits are paused")),
+        ContractStatus::DrawsPaused => Err(StdError::generic_err("Draws are paused")),
+        _ => Err(StdError::generic_err("This contract is stopped and this action is not allowed")),
+    }
+}
+
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
@@ -208,17 +452,33 @@ pub fn query<S: Storage, A: Api, Q: Querier>(
             let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
             let lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
             to_binary(&QueryAnswer::LotteryInfo {
+                round_id: lottery.round_id,
                 start_time: lottery.start_time,
                 end_time: lottery.end_time,
                 duration: lottery.duration,
-                is_stopped: config.is_stopped,
-                is_stopped_with_withdraw: config.is_stopped_can_withdraw,
+                weight_curve: lottery.weight_curve,
+                schedule_anchor: lottery.schedule_anchor,
+                is_stopped: is_stopped(config.status),
+                is_stopped_with_withdraw: config.status == ContractStatus::WithdrawOnly,
             })
         }
         QueryMsg::TotalRewards { height } => query_total_rewards(deps, height),
         QueryMsg::TotalDeposits {} => query_total_deposit(deps),
         QueryMsg::PastAllRecords {} => query_all_past_results(deps),
         QueryMsg::PastRecords {} => query_past_results(deps),
+        QueryMsg::Jackpot {} => query_jackpot(deps),
+        QueryMsg::RoundInfo { round_id } => query_round_info(deps, round_id),
+        QueryMsg::TicketInfo {} => query_ticket_info(deps),
+        QueryMsg::PendingConfigChanges {} => query_pending_config_changes(deps),
+        QueryMsg::Roles {} => query_roles(deps),
+        QueryMsg::QueuedActions {} => query_queued_actions(deps),
+        QueryMsg::Proposals {} => query_proposals(deps),
+        QueryMsg::ContractStatus {} => query_contract_status(deps),
+        QueryMsg::StakingMigrations {} => query_staking_migrations(deps),
+        QueryMsg::Config {} => query_config(deps),
+        QueryMsg::TokenInfo {} => query_token_info(deps),
+        QueryMsg::IncentivizedToken {} => query_incentivized_token(deps),
+        QueryMsg::Reconcile {} => query_reconcile(deps),
 
         //Temporary functions
 
@@ -234,7 +494,7 @@ pub fn authenticated_queries<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
-    let (address, key) = msg.get_validation_params();
+    let (address, key) = msg.get_validation_params()?;
 
     let vk_store = ReadonlyPrefixedStorage::new(VIEWING_KEY_KEY, &deps.storage);
     let expected_key = vk_store.get(address.0.as_bytes());
@@ -246,11 +506,14 @@ pub fn authenticated_queries<S: Storage, A: Api, Q: Querier>(
     } else if key.check_viewing_key(expected_key.unwrap().as_slice()) {
         return match msg {
             QueryMsg::Balance { address, .. } => query_deposit(deps, &address),
-            QueryMsg::AvailableTokensForWithdrawl { address, .. } => query_available_funds(deps, &address),
+            QueryMsg::AvailableTokensForWithdrawl { address, time, .. } => query_available_funds(deps, &address, time),
             QueryMsg::UserPastRecords { address, .. } => query_user_past_records(deps, address),
             QueryMsg::UserAllPastRecords { address, .. } => query_user_all_past_records(deps, address),
+            QueryMsg::Tickets { address, .. } => query_tickets(deps, &address),
+            QueryMsg::PendingUnbondings { address, .. } => query_pending_unbondings(deps, &address),
+            QueryMsg::EarlyExitFee { address, amount, time, .. } => query_early_exit_fee(deps, &address, amount, time),
 
-            _ => panic!("Unavailable or unknown query message"),
+            _ => Err(StdError::generic_err("Unavailable or unknown query message")),
         };
     }
 
@@ -322,10 +585,6 @@ fn receive<S: Storage, A: Api, Q: Querier>(
     }
 }
 
-fn valid_amount(amt: Uint128) -> bool {
-    amt >= Uint128(1000000)
-}
-
 fn deposit<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
@@ -343,31 +602,63 @@ fn deposit<S: Storage, A: Api, Q: Querier>(
             config.token.address, env.message.sender
         )));
     }
-    // Checking if the deposit is greater than 1 sefi
-    if !valid_amount(amount_to_deposit) {
+    //The entry set is frozen between CommitDraw and RevealDraw
+    if entries_frozen(&deps.storage, &config, env.block.height) {
         return Err(StdError::generic_err(
-            "Must deposit a minimum of 1000000 usefi, or 1 sefi",
+            "A draw is in progress. Deposits are available again once it is revealed",
         ));
     }
+    // Checking the deposit against the configured limits
+    if amount_to_deposit < config.min_deposit {
+        return Err(StdError::generic_err(format!(
+            "Must deposit a minimum of {} usefi", config.min_deposit
+        )));
+    }
 
     //UPDATING USER DATA
     let user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, from.0.as_bytes()], &deps.storage);
     let user_store = TypedStore::attach(&user_prefixed);
-    let mut user = user_store.load(from.0.as_bytes())
-        .unwrap_or(UserInfo { amount_delegated: Uint128(0), available_tokens_for_withdraw: Uint128(0), total_won: Uint128(0), entries: vec![], entry_index: vec![] }); // NotFound is the only possible error
+    let mut user: UserInfo = user_store.load(from.0.as_bytes())
+        .unwrap_or(UserInfo::default()); // NotFound is the only possible error
+    let user_before = user.clone();
+
+    if let Some(max_deposit_per_user) = config.max_deposit_per_user {
+        let delegated = user.amount_delegated.0.checked_add(amount_to_deposit.0)
+            .ok_or_else(|| StdError::generic_err("Deposit amount overflows the delegated amount"))?;
+        if delegated > max_deposit_per_user.0 {
+            return Err(StdError::generic_err(format!(
+                "Deposit would take this address over the limit of {} usefi delegated per address", max_deposit_per_user
+            )));
+        }
+    }
+    if let Some(pool_cap) = config.pool_cap {
+        let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
+        let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
+        let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+        let pool_total = supply_pool.total_tokens_staked.0.checked_add(amount_to_deposit.0)
+            .ok_or_else(|| StdError::generic_err("Deposit amount overflows the pool total"))?;
+        if pool_total > pool_cap.0 {
+            return Err(StdError::generic_err(format!(
+                "Deposit would take the pool over its cap of {} usefi", pool_cap
+            )));
+        }
+    }
+    let delegated_before = user.amount_delegated;
     user.amount_delegated += amount_to_deposit;
-
-    let mut lottery_entries = PrefixedStorage::multilevel(&[LOTTERY_ENTRY_KEY], &mut deps.storage);
-    let mut lottery_entries_append = GenerationalStoreMut::<LotteryEntries, PrefixedStorage<S>>::attach_or_create(&mut lottery_entries)?;
-    user.entry_index.push(lottery_entries_append.insert(LotteryEntries {
-        user_address: from.clone(),
-        amount: amount_to_deposit,
-        entry_time: env.block.time,
-    }));
+    update_user_entry(&mut deps.storage, &config, &from, &mut user, delegated_before, env.block.time)?;
+    if env.block.time >= user.recent_deposit.entry_time + config.early_exit_window {
+        user.recent_deposit.amount = Uint128(0);
+    }
+    //Merged deposits enter at their amount-weighted time, a small top-up does not restart the window for the rest
+    let recent_amount = user.recent_deposit.amount + amount_to_deposit;
+    let time_since_entry = Uint128((env.block.time - user.recent_deposit.entry_time.min(env.block.time)) as u128);
+    user.recent_deposit.entry_time = env.block.time - time_since_entry.multiply_ratio(user.recent_deposit.amount.0, recent_amount.0).0 as u64;
+    user.recent_deposit.amount = recent_amount;
 
     let mut user_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, from.0.as_bytes()], &mut deps.storage);
     let mut user_store = TypedStoreMut::attach(&mut user_prefixed);
     user_store.store(from.0.as_bytes(), &user)?;
+    track_user_totals(&mut deps.storage, &user_before, &user)?;
 
     //QUERYING PENDING_REWARDS
     let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
@@ -410,13 +701,23 @@ fn trigger_withdraw<S: Storage, A: Api, Q: Querier>(
     env: Env,
     amount: Option<Uint128>,
 ) -> StdResult<HandleResponse> {
+    //The entry set is frozen between CommitDraw and RevealDraw
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    if entries_frozen(&deps.storage, &config, env.block.height) {
+        return Err(StdError::generic_err(
+            "A draw is in progress. Withdrawals are available again once it is revealed",
+        ));
+    }
 
     //LOADING USER INFO
     let user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, env.message.sender.0.as_bytes()], &deps.storage);
     let user_store = TypedStore::attach(&user_prefixed);
     let mut user = user_store
         .load(env.message.sender.0.as_bytes())
-        .unwrap_or(UserInfo { amount_delegated: Uint128(0), available_tokens_for_withdraw: Uint128(0), total_won: Uint128(0), entries: vec![], entry_index: vec![] });
+        .unwrap_or(UserInfo::default());
+    let user_before = user.clone();
 
     //If withdraw amount in not send then all delegated amount is unstaked
     let withdraw_amount = amount.unwrap_or(user.amount_delegated);
@@ -429,43 +730,42 @@ fn trigger_withdraw<S: Storage, A: Api, Q: Querier>(
         return Err(StdError::generic_err("Trying to withdrawing more amount than staked"));
     }
 
+    //The fee stays staked and is added to the prize
+    let early_exit_fee = early_exit_fee(&config, &user, withdraw_amount, env.block.time);
+    let amount_to_redeem = (withdraw_amount - early_exit_fee)?;
+
     //Updating User Info
     user.amount_delegated = (user.amount_delegated - withdraw_amount).unwrap();
-    user.available_tokens_for_withdraw += withdraw_amount;
-
-    //Updating Lottery Entries
-    let mut temp_withdraw_amount = withdraw_amount.clone();
-    let mut lottery_entries = PrefixedStorage::multilevel(&[LOTTERY_ENTRY_KEY], &mut deps.storage);
-    let mut lottery_entries_store = GenerationalStoreMut::<LotteryEntries, PrefixedStorage<S>>::attach_or_create(&mut lottery_entries)?;
-    for ind in user.clone().entry_index {
-        let entry = lottery_entries_store.get(ind.clone()).unwrap();
-        if entry.amount == temp_withdraw_amount {
-            temp_withdraw_amount = Uint128(0);
-            let _ = lottery_entries_store.remove(ind.clone());
-            user.entry_index.retain(|index| index.borrow().clone() != ind);
-        } else if entry.amount < temp_withdraw_amount {
-            temp_withdraw_amount = (temp_withdraw_amount - entry.amount).unwrap();
-            let _ = lottery_entries_store.remove(ind.clone());
-            user.entry_index.retain(|index| index.borrow().clone() != ind);
-        } else {
-            let _ = lottery_entries_store.update(ind, LotteryEntries {
-                user_address: entry.user_address,
-                amount: (entry.amount - temp_withdraw_amount).unwrap(),
-                entry_time: entry.entry_time,
-            });
-            break;
+    //Withdrawals are taken from older deposits first
+    if user.recent_deposit.amount > user.amount_delegated {
+        user.recent_deposit.amount = user.amount_delegated;
+    }
+    if config.unbonding_period == 0 {
+        user.available_tokens_for_withdraw += amount_to_redeem;
+    } else {
+        release_unbondings(&mut user, env.block.time);
+        if user.unbondings.len() >= MAX_PENDING_UNBONDINGS {
+            return Err(StdError::generic_err(format!(
+                "There are already {} unbondings pending. Wait for one to mature", MAX_PENDING_UNBONDINGS
+            )));
         }
+        user.unbondings.push(UnbondingRecord {
+            amount: amount_to_redeem,
+            start_time: env.block.time,
+            release_time: env.block.time + config.unbonding_period,
+        });
     }
 
+    //Updating Lottery Entries
+    update_user_entry(&mut deps.storage, &config, &env.message.sender, &mut user, user.amount_delegated + withdraw_amount, env.block.time)?;
+
     //Updating UserInfo
     let mut user_mut_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, env.message.sender.0.as_bytes()], &mut deps.storage);
     let mut user_mut_store = TypedStoreMut::attach(&mut user_mut_prefixed);
     user_mut_store.store(env.message.sender.0.as_bytes(), &user)?;
+    track_user_totals(&mut deps.storage, &user_before, &user)?;
 
     //Updating Supply store
-    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
-    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
-    let config: Config = configstore.load(CONFIG_KEY)?;
     let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
     let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
     let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
@@ -474,27 +774,43 @@ fn trigger_withdraw<S: Storage, A: Api, Q: Querier>(
         supply_pool.pending_staking_rewards += staking_rewards_response.rewards.rewards
     }
     supply_pool.total_tokens_staked = (supply_pool.total_tokens_staked - withdraw_amount).unwrap();
+    supply_pool.total_rewards_restaked += early_exit_fee;
+    supply_pool.early_exit_fees += early_exit_fee;
+    supply_pool.total_pending_withdrawals += amount_to_redeem;
     supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
 
+    //Once stopped the stake may already be back with this contract, from EmergencyRedeemFromStaking or the
+    //invariant breaker. Only what is still staked is redeemed, the rest is paid from this contract's balance.
+    let mut amount_to_unstake = amount_to_redeem;
+    if is_stopped(config.status) {
+        let staked_balance = query_staked_balance(&deps, &config)?;
+        if staked_balance < amount_to_unstake {
+            amount_to_unstake = staked_balance;
+        }
+    }
+
     //Sending message for Withdraw
     let mut messages: Vec<CosmosMsg> = vec![];
-    messages.push(
-        WasmMsg::Execute {
-            contract_addr: config.staking_contract.address.clone(),
-            callback_code_hash: config.staking_contract.contract_hash.clone(),
-            msg: to_binary(&LPStakingHandleMsg::Redeem {
-                amount: Uint128::from(withdraw_amount)
-            })?,
-            send: vec![],
-        }
-            .into()
-    );
+    if amount_to_unstake > Uint128(0) {
+        messages.push(
+            WasmMsg::Execute {
+                contract_addr: config.staking_contract.address.clone(),
+                callback_code_hash: config.staking_contract.contract_hash.clone(),
+                msg: to_binary(&LPStakingHandleMsg::Redeem {
+                    amount: amount_to_unstake
+                })?,
+                send: vec![],
+            }
+                .into()
+        );
+    }
 
     Ok(HandleResponse {
         messages,
         log: vec![],
         data: Some(to_binary(&HandleAnswer::TriggerWithdraw {
             status: Success,
+            early_exit_fee,
         })?),
     })
 }
@@ -507,26 +823,30 @@ fn withdraw<S: Storage, A: Api, Q: Querier>(
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
-
-    //If contract is stopped but users are not allowed to withdraw
-    if config.is_stopped && !config.is_stopped_can_withdraw {
-        return Err(StdError::generic_err(
-            "This contract is stopped and this action is not allowed",
-        ));
-    }
+    let withdraw_only = config.status == ContractStatus::WithdrawOnly;
 
     let mut user_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, env.message.sender.0.as_bytes()], &mut deps.storage);
     let mut user_store = TypedStoreMut::<UserInfo, PrefixedStorage<'_, S>>::attach(&mut user_prefixed);
     let mut user = user_store
         .load(env.message.sender.0.as_bytes())
-        .unwrap_or(UserInfo { amount_delegated: Uint128(0), available_tokens_for_withdraw: Uint128(0), total_won: Uint128(0), entries: vec![], entry_index: vec![] }); // NotFound is the only possible error
+        .unwrap_or(UserInfo::default()); // NotFound is the only possible error
+    let user_before = user.clone();
+
+    //Matured unbondings become available, and all of them once the contract is stopped with withdrawals allowed
+    if withdraw_only {
+        release_unbondings(&mut user, u64::MAX);
+    } else {
+        release_unbondings(&mut user, env.block.time);
+    }
 
     let withdraw_amount = amount.unwrap_or(user.available_tokens_for_withdraw);
     if withdraw_amount <= Uint128(0) {
         return Err(StdError::generic_err("No tokens available for withdraw"));
     }
 
-    if !config.is_stopped_can_withdraw {
+    //Split between what was held for the user and, once stopped with withdrawals allowed, what was delegated
+    let mut from_delegated = Uint128(0);
+    if !withdraw_only {
         if user.available_tokens_for_withdraw < withdraw_amount {
             return Err(StdError::generic_err("Withdrawing more amount than Available tokens for withdraw"));
         }
@@ -534,22 +854,38 @@ fn withdraw<S: Storage, A: Api, Q: Querier>(
         user.available_tokens_for_withdraw = (user.available_tokens_for_withdraw - withdraw_amount).unwrap();
         user_store.store(env.message.sender.0.as_bytes(), &user)?;
     }
-    if config.is_stopped_can_withdraw {
+    if withdraw_only {
         if user.amount_delegated + user.available_tokens_for_withdraw < withdraw_amount {
             return Err(StdError::generic_err("Withdrawing more amount than Total Delegated and Reduced Staked tokens"));
         }
+        let delegated_before = user.amount_delegated;
         if user.available_tokens_for_withdraw < withdraw_amount {
             let temp_variable = (withdraw_amount - user.available_tokens_for_withdraw).unwrap();
             user.available_tokens_for_withdraw = Uint128(0);
             user.amount_delegated = (user.amount_delegated - temp_variable).unwrap();
+            from_delegated = temp_variable;
         } else {
             user.available_tokens_for_withdraw = (user.available_tokens_for_withdraw - withdraw_amount).unwrap();
         }
+        update_user_entry(&mut deps.storage, &config, &env.message.sender, &mut user, delegated_before, env.block.time)?;
+        if user.recent_deposit.amount > user.amount_delegated {
+            user.recent_deposit.amount = user.amount_delegated;
+        }
+        let mut user_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, env.message.sender.0.as_bytes()], &mut deps.storage);
+        let mut user_store = TypedStoreMut::<UserInfo, PrefixedStorage<'_, S>>::attach(&mut user_prefixed);
         user_store.store(env.message.sender.0.as_bytes(), &user)?;
     }
+    track_user_totals(&mut deps.storage, &user_before, &user)?;
+
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    supply_pool.total_tokens_staked = (supply_pool.total_tokens_staked - from_delegated)?;
+    supply_pool.total_pending_withdrawals = (supply_pool.total_pending_withdrawals - (withdraw_amount - from_delegated)?)?;
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
 
     let messages: Vec<CosmosMsg> = vec![
-        // Transfer Trigger fee to triggerer wallet
+        // Transfer the withdrawn amount to the user
         transfer_msg(
             env.message.sender,
             withdraw_amount,
@@ -570,80 +906,142 @@ fn withdraw<S: Storage, A: Api, Q: Querier>(
 }
 
 //Triggerer
+fn commit_draw<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    entropy_hash: Binary,
+) -> StdResult<HandleResponse> {
+    //Checking if msg send by Triggerer, unless anyone may trigger draws
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    if !config.permissionless_draws {
+        check_role(&deps.storage, Role::Triggerer, &env.message.sender)?;
+    }
+
+    if entropy_hash.0.len() != 32 {
+        return Err(StdError::generic_err("Entropy hash must be the sha256 hash of the secret entropy"));
+    }
+
+    //A draw can only be committed once the lottery has ended
+    let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &deps.storage);
+    let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
+    let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
+    validate_end_time(a_lottery.end_time, env.block.time)?;
+
+    //Letting a commitment lapse must not buy a second try at the same round.
+    //Triggerers are trusted with the draw and are not barred, a missed reveal would otherwise stall the round.
+    let mut lapsed = load_lapsed_committers(&deps.storage)?;
+    if lapsed.round_id != a_lottery.round_id {
+        lapsed = LapsedCommitters { round_id: a_lottery.round_id, committers: vec![] };
+    }
+    let unrevealed = match load_pending_draw_commitment(&deps.storage, &config, env.block.height) {
+        //A Triggerer can take over a permissionless commitment once it could have been revealed
+        Some(pending) => {
+            let replaceable = !has_role(&deps.storage, Role::Triggerer, &pending.committer)?
+                && has_role(&deps.storage, Role::Triggerer, &env.message.sender)?
+                && env.block.height > pending.commit_height + config.draw_reveal_delay;
+            if !replaceable {
+                return Err(StdError::generic_err("A draw has already been committed and is waiting to be revealed"));
+            }
+            Some(pending)
+        }
+        None => load_draw_commitment(&deps.storage)?,
+    };
+    if let Some(unrevealed) = unrevealed {
+        if unrevealed.round_id == a_lottery.round_id
+            && !lapsed.committers.contains(&unrevealed.committer)
+            && !has_role(&deps.storage, Role::Triggerer, &unrevealed.committer)? {
+            lapsed.committers.push(unrevealed.committer);
+        }
+    }
+    if lapsed.committers.contains(&env.message.sender) && !has_role(&deps.storage, Role::Triggerer, &env.message.sender)? {
+        return Err(StdError::generic_err(
+            "This address let its commitment for this round lapse and can not commit again until the round is drawn",
+        ));
+    }
+    store_lapsed_committers(&mut deps.storage, &lapsed)?;
+
+    let mut commitment_prefixed = PrefixedStorage::multilevel(&[DRAW_COMMITMENT_KEY_PREFIX], &mut deps.storage);
+    let mut commitment_store = TypedStoreMut::<DrawCommitment, PrefixedStorage<'_, S>>::attach(&mut commitment_prefixed);
+    commitment_store.store(
+        DRAW_COMMITMENT_KEY,
+        &DrawCommitment {
+            entropy_hash: entropy_hash.0,
+            committer: env.message.sender,
+            commit_height: env.block.height,
+            commit_time: env.block.time,
+            round_id: a_lottery.round_id,
+        },
+    )?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CommitDraw {
+            status: Success,
+            reveal_height: env.block.height + config.draw_reveal_delay,
+        })?),
+    })
+}
+
+fn reveal_draw<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    entropy: Binary,
+) -> StdResult<HandleResponse> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    let commitment = match load_pending_draw_commitment(&deps.storage, &config, env.block.height) {
+        Some(commitment) => commitment,
+        None => return Err(StdError::generic_err("No draw has been committed or the commitment has expired")),
+    };
+    if commitment.committer != env.message.sender {
+        return Err(StdError::generic_err("Only the address that committed the draw can reveal it"));
+    }
+    if env.block.height < commitment.commit_height + config.draw_reveal_delay {
+        return Err(StdError::generic_err(format!(
+            "Draw can be revealed from block {}", commitment.commit_height + config.draw_reveal_delay
+        )));
+    }
+    if !ct_slice_compare(&sha_256(&entropy.0), &commitment.entropy_hash) {
+        return Err(StdError::generic_err("Revealed entropy does not match the committed hash"));
+    }
+
+    let mut commitment_prefixed = PrefixedStorage::multilevel(&[DRAW_COMMITMENT_KEY_PREFIX], &mut deps.storage);
+    commitment_prefixed.remove(DRAW_COMMITMENT_KEY);
+
+    //Everything but the secret is fixed by the commitment, so the block the reveal lands in changes nothing
+    let mut draw_entropy: Vec<u8> = vec![];
+    draw_entropy.extend(&(commitment.commit_height + config.draw_reveal_delay).to_be_bytes());
+    draw_entropy.extend(&commitment.commit_time.to_be_bytes());
+    draw_entropy.extend(&entropy.0);
+
+    claim_rewards(deps, env, &draw_entropy)
+}
+
 fn claim_rewards<'a, S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    draw_entropy: &[u8],
 ) -> StdResult<HandleResponse> {
-    //Checking if msg send by Triggerer
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_triggerer(&config, &env.message.sender)?;
+    let pending_changes = load_pending_config_changes(&deps.storage)?;
 
     //Checking if start time starts
-    let mut lottery_prefixed = PrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mut deps.storage);
-    let mut lottery_store = TypedStoreMut::<Lottery, PrefixedStorage<'_, S>>::attach(&mut lottery_prefixed);
+    let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &deps.storage);
+    let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
     let mut a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
     validate_end_time(a_lottery.end_time, env.block.time)?;
     validate_start_time(a_lottery.start_time, env.block.time)?;
 
     // This way every time we call the claim_rewards function we will get a different result.
-    // Plus it's going to be pretty hard to predict the exact time of the block, so less chance of cheating
-    a_lottery.entropy.extend(&env.block.height.to_be_bytes());
-    a_lottery.entropy.extend(&env.block.time.to_be_bytes());
-    a_lottery.start_time = &env.block.time + 0;
-    a_lottery.end_time = &env.block.time + a_lottery.duration + 0;
-    lottery_store.store(LOTTERY_KEY, &a_lottery)?;
-
-    //Launching the lottery
-    let lottery_entries_append: GenerationalStore::<LotteryEntries, ReadonlyPrefixedStorage<S>>;
-    let lottery_entries = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_ENTRY_KEY], &deps.storage);
-    if let Ok(res) = GenerationalStore::<LotteryEntries, ReadonlyPrefixedStorage<S>>::attach(&lottery_entries).unwrap_or(
-        Err(StdError::generic_err("Lottery Restarted. Error due to no entries "))
-    ) {
-        lottery_entries_append = res;
-    } else {
-        return Ok(HandleResponse {
-            messages: vec![],
-            log: vec![],
-            data: Some(to_binary(&HandleAnswer::ClaimRewards {
-                status: Failure,
-                winner: HumanAddr("Lottery Restarted. Error due to no entries ".to_string()),
-            })?),
-        });
-    }
-    let data = lottery_entries_append;
-    if data.iter().count() == 0 {
-        return Ok(HandleResponse {
-            messages: vec![],
-            log: vec![],
-            data: Some(to_binary(&HandleAnswer::ClaimRewards {
-                status: Failure,
-                winner: HumanAddr("Lottery Restarted. Error due to no entries ".to_string()),
-            })?),
-        });
-    }
-
-    //Choosing Winner
-    let mut entries: Vec<HumanAddr> = vec![];
-    let mut weights: Vec<u128> = vec![];
-    let iterator = data.iter().filter(|item| matches!(item, (_, Entry::Occupied { .. })));
-    for user_address in iterator {
-        let user_address = match user_address.1 {
-            Entry::Occupied { generation: _, value } => value,
-            _ => panic!("Unexpected result "),
-        };
-        if a_lottery.end_time <= user_address.entry_time {
-            entries.push(user_address.user_address);
-            weights.push(0 as u128)
-        } else if ((&a_lottery.end_time - user_address.entry_time) / &a_lottery.duration) >= 1 {
-            entries.push(user_address.user_address);
-            weights.push(user_address.amount.0)
-        } else {
-            entries.push(user_address.user_address);
-            weights.push((user_address.amount.0 / 1000000) * ((((a_lottery.end_time - user_address.entry_time) * 1000000) / a_lottery.duration) as u128))
-        }
-    }
+    // The draw entropy only holds what the commitment fixed, the reveal block is left out so it can not be chosen
+    a_lottery.entropy.extend(draw_entropy);
     let prng_seed = config.clone().prng_seed;
     let mut hasher = Sha256::new();
     hasher.update(&prng_seed);
@@ -651,22 +1049,82 @@ fn claim_rewards<'a, S: Storage, A: Api, Q: Querier>(
     let hash = hasher.finalize();
     let mut result = [0u8; 32];
     result.copy_from_slice(hash.as_slice());
+
+    //Record of the round being drawn, completed below and stored once
+    let mut round_record = RoundRecord {
+        round_id: a_lottery.round_id,
+        start_time: a_lottery.start_time,
+        end_time: a_lottery.end_time,
+        duration: a_lottery.duration,
+        total_weight: Uint128(0),
+        participants: 0,
+        entropy_hash: Binary(sha_256(&result).to_vec()),
+        winners: vec![],
+        prize: Uint128(0),
+        triggerer_fee: Uint128(0),
+        status: RoundStatus::RolledOver,
+        weight_curve: a_lottery.weight_curve.clone(),
+        winning_tickets: vec![],
+    };
+
+    //Launching the lottery
+    //Choosing Winners
+    let (entry_tree_prefix, deficit_tree_prefix) = draw_index_prefixes(&config, &round_record.weight_curve);
+    let (holders, total_weight) = {
+        let slot_registry_prefixed = ReadonlyPrefixedStorage::multilevel(&[SLOT_REGISTRY_KEY_PREFIX], &deps.storage);
+        let slot_registry_store = TypedStore::<SlotRegistry, ReadonlyPrefixedStorage<'_, S>>::attach(&slot_registry_prefixed);
+        let slot_registry: SlotRegistry = slot_registry_store.load(SLOT_REGISTRY_KEY)?;
+        let entry_tree = ReadonlyPrefixedStorage::multilevel(&[entry_tree_prefix], &deps.storage);
+        let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[deficit_tree_prefix, &round_record.round_id.to_be_bytes()], &deps.storage);
+        (slot_registry.holders, fenwick::net_total(&entry_tree, &deficit_tree)?)
+    };
+    //A round that rolls over is not drawn, the pending changes wait for the next draw
+    let drawn = holders > 0 && total_weight > 0;
+
+    a_lottery.round_id += 1;
+    if drawn {
+        a_lottery.weight_curve = pending_changes.weight_curve.clone().unwrap_or_else(|| config.weight_curve.clone());
+        if let Some(duration) = pending_changes.duration {
+            a_lottery.duration = duration;
+        }
+    }
+    match a_lottery.schedule_anchor {
+        //A late draw does not move the schedule, boundaries missed while waiting are skipped
+        Some(anchor) => {
+            a_lottery.end_time = next_boundary(anchor, a_lottery.duration, env.block.time);
+            a_lottery.start_time = a_lottery.end_time - a_lottery.duration;
+        }
+        None => {
+            a_lottery.start_time = &env.block.time + 0;
+            a_lottery.end_time = &env.block.time + a_lottery.duration + 0;
+        }
+    }
+    let mut lottery_prefixed = PrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mut deps.storage);
+    let mut lottery_store = TypedStoreMut::<Lottery, PrefixedStorage<'_, S>>::attach(&mut lottery_prefixed);
+    lottery_store.store(LOTTERY_KEY, &a_lottery)?;
+
+    if holders == 0 {
+        return roll_over_prize(deps, &env, round_record, "Lottery Restarted. Error due to no entries");
+    }
+    if total_weight == 0 {
+        return roll_over_prize(deps, &env, round_record, "NONE!!! All entries had weight zero. Lottery restarted");
+    }
+    //The round being drawn keeps the config loaded above
+    apply_pending_config_changes(&mut deps.storage, pending_changes)?;
+    round_record.total_weight = Uint128(total_weight);
+    round_record.participants = holders;
+
+    //One draw per prize tier from the same stream, without replacement
     let mut rng: ChaChaRng = ChaChaRng::from_seed(result);
-    let dist: WeightedIndex<u128>;
-    if let Ok(distribution) = WeightedIndex::new(&weights) {
-        dist = distribution
-    } else {
-        return Ok(HandleResponse {
-            messages: vec![],
-            log: vec![],
-            data: Some(to_binary(&HandleAnswer::ClaimRewards {
-                status: Success,
-                winner: HumanAddr("NONE!!! All entries had weight zero. Lottery restarted".to_string()),
-            })?),
-        });
+    let mut winners_human: Vec<HumanAddr> = vec![];
+    let draw = draw_winners(&deps.storage, entry_tree_prefix, deficit_tree_prefix, round_record.round_id, &mut rng, config.prize_tiers.len())?;
+    for (winner_human, position) in draw {
+        winners_human.push(winner_human);
+        //In ticket mode the position drawn is the winning ticket number
+        if config.ticket_size.is_some() {
+            round_record.winning_tickets.push(Uint128(position));
+        }
     }
-    let sample = dist.sample(&mut rng);
-    let winner_human = entries[sample].clone();
 
     //Getting the pending_rewards
     let response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
@@ -675,13 +1133,29 @@ fn claim_rewards<'a, S: Storage, A: Api, Q: Querier>(
     let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
     let mut winning_amount = supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards + response.rewards.rewards;
 
-    let trigger_percentage = config.triggerer_share_percentage;
-    let trigger_share = Uint128(winning_amount.0 * ((trigger_percentage * 1000000) as u128) / 10000000000);
-    winning_amount = (winning_amount - trigger_share).unwrap();
-    supply_pool.triggering_cost = trigger_share;
+    let mut trigger_share = winning_amount.multiply_ratio(config.fees.triggerer_fee_bps, 10000u64);
+    //In permissionless mode the caller is paid on the spot, up to the bounty cap. Whatever is above the cap stays in the prize.
+    if config.permissionless_draws {
+        if trigger_share > config.max_triggerer_bounty {
+            trigger_share = config.max_triggerer_bounty;
+        }
+    } else {
+        supply_pool.triggering_cost += trigger_share;
+    }
+    let protocol_fee = winning_amount.multiply_ratio(config.fees.protocol_fee_bps, 10000u64);
+    let charity_fee = winning_amount.multiply_ratio(config.fees.charity_fee_bps, 10000u64);
+    supply_pool.protocol_fees += protocol_fee;
+    supply_pool.charity_fees += charity_fee;
+    winning_amount = (winning_amount - (trigger_share + protocol_fee + charity_fee))?;
+    //The prize is paid into the winners' available tokens
+    supply_pool.total_pending_withdrawals += winning_amount;
     supply_pool.pending_staking_rewards = Uint128(0);
     let redeeming_amount = supply_pool.total_rewards_restaked;
     supply_pool.total_rewards_restaked = Uint128(0);
+    //Any rolled over prize was part of the rewards just won
+    supply_pool.rolled_over_prize = Uint128(0);
+    supply_pool.rolled_over_rounds = 0;
+    supply_pool.early_exit_fees = Uint128(0);
     supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
     if winning_amount == Uint128(0) {
         return Err(StdError::generic_err(
@@ -701,57 +1175,203 @@ fn claim_rewards<'a, S: Storage, A: Api, Q: Querier>(
         }
             .into()
     );
+    if config.permissionless_draws && trigger_share > Uint128(0) {
+        messages.push(transfer_msg(
+            env.message.sender.clone(),
+            trigger_share,
+            None,
+            RESPONSE_BLOCK_SIZE,
+            config.token.contract_hash.clone(),
+            config.token.address.clone(),
+        )?);
+    }
 
-    let mut user_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, winner_human.0.as_bytes()], &mut deps.storage);
-    let mut user_store = TypedStoreMut::<UserInfo, PrefixedStorage<'_, S>>::attach(&mut user_prefixed);
-    let mut user = user_store.load(winner_human.0.as_bytes()).unwrap(); // NotFound is the only possible error
-    user.total_won += winning_amount;
-    user.available_tokens_for_withdraw += winning_amount;
-    user_store.store(winner_human.0.as_bytes(), &user)?;
-
-    let mut user_history = PrefixedStorage::multilevel(&[USER_WINNING_HISTORY_KEY, winner_human.0.as_bytes()], &mut deps.storage);
-    let mut user_history_append = AppendStoreMut::attach_or_create(&mut user_history)?;
-    user_history_append.push(&UserWinningHistory { winning_amount: winning_amount.0 as u64, time: env.block.time })?;
+    //Splitting the prize between the tiers. Rounding dust and the share of any tier
+    //that could not be filled (fewer participants than tiers) go to the first tier.
+    let mut winners: Vec<(HumanAddr, Uint128)> = vec![];
+    let mut distributed_amount = Uint128(0);
+    for (winner_human, tier) in winners_human.iter().zip(config.prize_tiers.iter()).skip(1) {
+        let tier_amount = winning_amount.multiply_ratio(*tier, 100u64);
+        distributed_amount += tier_amount;
+        winners.push((winner_human.clone(), tier_amount));
+    }
+    winners.insert(0, (winners_human[0].clone(), (winning_amount - distributed_amount)?));
+
+    for (winner_human, prize) in winners.iter() {
+        let mut user_prefixed = PrefixedStorage::multilevel(&[USER_INFO_KEY, winner_human.0.as_bytes()], &mut deps.storage);
+        let mut user_store = TypedStoreMut::<UserInfo, PrefixedStorage<'_, S>>::attach(&mut user_prefixed);
+        let mut user = user_store.load(winner_human.0.as_bytes()).unwrap(); // NotFound is the only possible error
+        let user_before = user.clone();
+        user.total_won += *prize;
+        user.available_tokens_for_withdraw += *prize;
+        user_store.store(winner_human.0.as_bytes(), &user)?;
+        track_user_totals(&mut deps.storage, &user_before, &user)?;
+
+        let mut user_history = PrefixedStorage::multilevel(&[USER_WINNING_HISTORY_KEY, winner_human.0.as_bytes()], &mut deps.storage);
+        let mut user_history_append = AppendStoreMut::attach_or_create(&mut user_history)?;
+        user_history_append.push(&UserWinningHistory { winning_amount: prize.0 as u64, time: env.block.time })?;
+    }
 
     let mut last_lottery_result = PrefixedStorage::multilevel(&[LAST_LOTTERY_KEY], &mut deps.storage);
     let mut last_lottery_result_append = AppendStoreMut::attach_or_create(&mut last_lottery_result)?;
-    last_lottery_result_append.push(&LastLotteryResults { winning_amount: winning_amount.0 as u64, time: env.block.time })?;
+    last_lottery_result_append.push(&LastLotteryResults {
+        winning_amount: winning_amount.0 as u64,
+        time: env.block.time,
+        winners: winners.clone(),
+        rolled_over: false,
+    })?;
+
+    round_record.winners = winners.clone();
+    round_record.prize = winning_amount;
+    round_record.triggerer_fee = trigger_share;
+    round_record.status = RoundStatus::Drawn;
+    store_round_record(&mut deps.storage, &round_record)?;
+    clear_round_deficits(&mut deps.storage, round_record.round_id)?;
 
     Ok(HandleResponse {
         messages,
         log: vec![],
         data: Some(to_binary(&HandleAnswer::ClaimRewards {
             status: Success,
-            winner: winner_human,
+            winners,
+            rolled_over: false,
         })?),
     })
 }
 
-fn triggering_cost_withdraw<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    env: Env,
-) -> StdResult<HandleResponse> {
-    //Checking if admin
-    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
-    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
-    let config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+fn store_round_record<S: Storage>(storage: &mut S, round_record: &RoundRecord) -> StdResult<()> {
+    let mut round_prefixed = PrefixedStorage::multilevel(&[ROUND_KEY_PREFIX], storage);
+    let mut round_store = TypedStoreMut::<RoundRecord, PrefixedStorage<'_, S>>::attach(&mut round_prefixed);
+    round_store.store(&round_record.round_id.to_be_bytes(), round_record)
+}
 
-    //checking if triggering cost greater than 0
-    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
-    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
-    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
-    if supply_pool.triggering_cost == Uint128(0)
-    {
-        return Err(StdError::generic_err("Triggerer share not sufficient"));
+fn load_pending_config_changes<S: ReadonlyStorage>(storage: &S) -> StdResult<PendingConfigChanges> {
+    let pending_prefixed = ReadonlyPrefixedStorage::multilevel(&[PENDING_CONFIG_CHANGES_KEY_PREFIX], storage);
+    let pending_store = TypedStore::<PendingConfigChanges, ReadonlyPrefixedStorage<'_, S>>::attach(&pending_prefixed);
+    Ok(pending_store.may_load(PENDING_CONFIG_CHANGES_KEY)?.unwrap_or_default())
+}
+
+fn store_pending_config_changes<S: Storage>(storage: &mut S, pending_changes: &PendingConfigChanges) -> StdResult<()> {
+    let mut pending_prefixed = PrefixedStorage::multilevel(&[PENDING_CONFIG_CHANGES_KEY_PREFIX], storage);
+    let mut pending_store = TypedStoreMut::<PendingConfigChanges, PrefixedStorage<'_, S>>::attach(&mut pending_prefixed);
+    pending_store.store(PENDING_CONFIG_CHANGES_KEY, pending_changes)
+}
+
+/// apply_pending_config_changes writes the queued config changes and clears the queue.
+/// The duration is applied to the lottery by the caller.
+fn apply_pending_config_changes<S: Storage>(storage: &mut S, pending_changes: PendingConfigChanges) -> StdResult<()> {
+    if pending_changes == PendingConfigChanges::default() {
+        return Ok(());
+    }
+
+    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], storage);
+    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
+    let mut config: Config = configstore.load(CONFIG_KEY)?;
+    if let Some(fees) = pending_changes.fees {
+        config.fees = fees;
+    }
+    if let Some(tiers) = pending_changes.prize_tiers {
+        config.prize_tiers = tiers;
+    }
+    if let Some(curve) = pending_changes.weight_curve {
+        config.weight_curve = curve;
+    }
+    if let Some(period) = pending_changes.unbonding_period {
+        config.unbonding_period = period;
+    }
+    if let Some(early_exit_fee) = pending_changes.early_exit_fee {
+        config.early_exit_fee_percentage = early_exit_fee.percentage;
+        config.early_exit_window = early_exit_fee.window;
+    }
+    if let Some(limits) = pending_changes.deposit_limits {
+        config.min_deposit = limits.min_deposit;
+        config.max_deposit_per_user = limits.max_deposit_per_user;
+        config.pool_cap = limits.pool_cap;
+    }
+    if let Some(permissionless_draws) = pending_changes.permissionless_draws {
+        config.permissionless_draws = permissionless_draws.enabled;
+        config.max_triggerer_bounty = permissionless_draws.max_bounty;
+    }
+    configstore.store(CONFIG_KEY, &config)?;
+
+    store_pending_config_changes(storage, &PendingConfigChanges::default())
+}
+
+/// roll_over_prize keeps every unawarded reward earmarked for the next round's prize
+fn roll_over_prize<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    round_record: RoundRecord,
+    reason: &str,
+) -> StdResult<HandleResponse> {
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    //Early exit fees are part of it
+    supply_pool.rolled_over_prize = supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+    supply_pool.rolled_over_rounds += 1;
+    supply_pool.early_exit_fees = Uint128(0);
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
+
+    let mut last_lottery_result = PrefixedStorage::multilevel(&[LAST_LOTTERY_KEY], &mut deps.storage);
+    let mut last_lottery_result_append = AppendStoreMut::attach_or_create(&mut last_lottery_result)?;
+    last_lottery_result_append.push(&LastLotteryResults {
+        winning_amount: 0,
+        time: env.block.time,
+        winners: vec![],
+        rolled_over: true,
+    })?;
+    store_round_record(&mut deps.storage, &round_record)?;
+    clear_round_deficits(&mut deps.storage, round_record.round_id)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("result", reason),
+            log("rolled_over_prize", supply_pool.rolled_over_prize),
+        ],
+        data: Some(to_binary(&HandleAnswer::ClaimRewards {
+            status: Success,
+            winners: vec![],
+            rolled_over: true,
+        })?),
+    })
+}
+
+fn withdraw_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    //One address may be the recipient of several fees
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    let mut amount = Uint128(0);
+    if env.message.sender == config.fees.triggerer_fee_recipient {
+        amount += supply_pool.triggering_cost;
+        supply_pool.triggering_cost = Uint128(0);
+    }
+    if env.message.sender == config.fees.treasury {
+        amount += supply_pool.protocol_fees;
+        supply_pool.protocol_fees = Uint128(0);
+    }
+    if Some(&env.message.sender) == config.fees.charity.as_ref() {
+        amount += supply_pool.charity_fees;
+        supply_pool.charity_fees = Uint128(0);
+    }
+    if amount == Uint128(0) {
+        return Err(StdError::generic_err("No fees to withdraw for this address"));
     }
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
 
-    //Send triggering cost to admin
     let messages: Vec<CosmosMsg> = vec![
-        // Transfer Trigger fee to triggerer wallet
         transfer_msg(
-            config.admin,
-            supply_pool.triggering_cost,
+            env.message.sender,
+            amount,
             None,
             RESPONSE_BLOCK_SIZE,
             config.token.contract_hash.clone(),
@@ -759,13 +1379,10 @@ fn triggering_cost_withdraw<S: Storage, A: Api, Q: Querier>(
         )?
     ];
 
-    supply_pool.triggering_cost = Uint128(0);
-    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
-
     let res = HandleResponse {
         messages,
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::TriggeringCostWithdraw { status: Success })?),
+        data: Some(to_binary(&HandleAnswer::WithdrawFees { status: Success, amount })?),
     };
     Ok(res)
 }
@@ -774,28 +1391,30 @@ fn withdraw_excess<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
 ) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::FeeCollector, &env.message.sender)?;
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
 
     let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
     let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
     let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
-    let excess_amount = supply_pool.pending_staking_rewards + supply_pool.total_rewards_restaked;
+    //Rolled over prize and early exit fees are earmarked for the next draw and are never excess
+    let earmarked = supply_pool.rolled_over_prize + supply_pool.early_exit_fees;
+    let excess_amount = ((supply_pool.pending_staking_rewards + supply_pool.total_rewards_restaked) - earmarked)
+        .unwrap_or(Uint128(0));
     if excess_amount <= Uint128(0)
     {
-        return Err(StdError::generic_err("Triggerer share not sufficient"));
+        return Err(StdError::generic_err("There are no excess rewards to withdraw"));
     }
 
     supply_pool.pending_staking_rewards = Uint128(0);
-    supply_pool.total_rewards_restaked = Uint128(0);
+    supply_pool.total_rewards_restaked = earmarked;
     supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
 
     let messages: Vec<CosmosMsg> = vec![
-        // Transfer Trigger fee to triggerer wallet
         transfer_msg(
-            config.admin,
+            env.message.sender,
             excess_amount,
             None,
             RESPONSE_BLOCK_SIZE,
@@ -813,249 +1432,865 @@ fn withdraw_excess<S: Storage, A: Api, Q: Querier>(
 }
 
 //Admin COMMANDS ONLY
-fn change_admin<S: Storage, A: Api, Q: Querier>(
+//Only Owner and Triggerer are handed over, the proposal is stored until the proposed address accepts it
+fn pending_handover_slot(handover: &mut PendingHandover, role: Role) -> StdResult<&mut Option<HumanAddr>> {
+    match role {
+        Role::Owner => Ok(&mut handover.admin),
+        Role::Triggerer => Ok(&mut handover.triggerer),
+        _ => Err(StdError::generic_err("Only the admin and the triggerer are handed over")),
+    }
+}
+
+fn propose_handover<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    role: Role,
     address: HumanAddr,
 ) -> StdResult<HandleResponse> {
-    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
-    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
-    let mut config: Config = configstore.load(CONFIG_KEY)?;
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    //A malformed address could never accept
+    deps.api.canonical_address(&address)?;
 
-    check_if_admin(&config, &env.message.sender)?;
-    config.admin = address;
-    configstore.store(CONFIG_KEY, &config)?;
+    let mut handover = load_pending_handover(&deps.storage)?;
+    *pending_handover_slot(&mut handover, role)? = Some(address);
+    store_pending_handover(&mut deps.storage, &handover)?;
 
+    let answer = match role {
+        Role::Owner => HandleAnswer::ProposeAdmin { status: Success },
+        _ => HandleAnswer::ProposeTriggerer { status: Success },
+    };
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::ChangeAdmin { status: Success })?),
+        data: Some(to_binary(&answer)?),
     })
 }
 
-fn change_triggerer<S: Storage, A: Api, Q: Querier>(
+fn accept_handover<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    address: HumanAddr,
+    role: Role,
 ) -> StdResult<HandleResponse> {
-    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
-    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
-    let mut config: Config = configstore.load(CONFIG_KEY)?;
+    let mut handover = load_pending_handover(&deps.storage)?;
+    let slot = pending_handover_slot(&mut handover, role)?;
+    if slot.as_ref() != Some(&env.message.sender) {
+        return Err(StdError::generic_err("This address has not been proposed"));
+    }
+    *slot = None;
+    store_pending_handover(&mut deps.storage, &handover)?;
+    //An admin handover also hands over the other roles the outgoing owners held, holders that were not owners keep theirs
+    if role == Role::Owner {
+        let outgoing_owners = load_role_members(&deps.storage, Role::Owner)?;
+        for other_role in &[Role::Pauser, Role::ParameterManager, Role::FeeCollector] {
+            let mut members = load_role_members(&deps.storage, *other_role)?;
+            if members.iter().any(|member| outgoing_owners.contains(member)) {
+                members.retain(|member| !outgoing_owners.contains(member) && member != &env.message.sender);
+                members.push(env.message.sender.clone());
+                store_role_members(&mut deps.storage, *other_role, &members)?;
+            }
+        }
+    }
+    //The accepted address replaces every holder of the role
+    store_role_members(&mut deps.storage, role, &vec![env.message.sender])?;
 
-    check_if_admin(&config, &env.message.sender)?;
-    config.triggerer = address;
-    configstore.store(CONFIG_KEY, &config)?;
+    let answer = match role {
+        Role::Owner => HandleAnswer::AcceptAdmin { status: Success },
+        _ => HandleAnswer::AcceptTriggerer { status: Success },
+    };
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&answer)?),
+    })
+}
+
+fn cancel_handover<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    role: Role,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+
+    let mut handover = load_pending_handover(&deps.storage)?;
+    let slot = pending_handover_slot(&mut handover, role)?;
+    if slot.is_none() {
+        return Err(StdError::generic_err("There is no pending proposal"));
+    }
+    *slot = None;
+    store_pending_handover(&mut deps.storage, &handover)?;
 
+    let answer = match role {
+        Role::Owner => HandleAnswer::CancelAdminProposal { status: Success },
+        _ => HandleAnswer::CancelTriggererProposal { status: Success },
+    };
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::ChangeTriggerer { status: Success })?),
+        data: Some(to_binary(&answer)?),
     })
 }
 
-fn change_lottery_duration<S: Storage, A: Api, Q: Querier>(
+fn action_role(action: &TimelockedAction) -> Role {
+    match action {
+        TimelockedAction::WithdrawExcess {} => Role::FeeCollector,
+        _ => Role::Owner,
+    }
+}
+
+/// queue_action holds a timelocked action until its eta. EmergencyRedeemFromStaking is the one exception,
+/// on a stopped contract it runs at once instead of being queued.
+fn queue_action<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    duration: u64,
+    action: TimelockedAction,
 ) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, action_role(&action), &env.message.sender)?;
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
 
-    check_if_admin(&config, &env.message.sender)?;
+    //Redeeming only brings the funds back to this contract, once it is stopped that can not wait for the delay
+    if let TimelockedAction::EmergencyRedeemFromStaking {} = action {
+        if is_stopped(config.status) {
+            return emergency_redeem_from_staking(deps, env);
+        }
+    }
 
-    let mut lottery_prefixed = PrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mut deps.storage);
-    let mut lottery_store = TypedStoreMut::<Lottery, PrefixedStorage<'_, S>>::attach(&mut lottery_prefixed);
-    let mut a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
-    a_lottery.duration = duration;
-    lottery_store.store(LOTTERY_KEY, &a_lottery)?;
+    let mut queue = load_action_queue(&deps.storage)?;
+    if queue.actions.len() >= MAX_QUEUED_ACTIONS {
+        return Err(StdError::generic_err(format!("At most {} actions can be queued", MAX_QUEUED_ACTIONS)));
+    }
+    let id = queue.next_id;
+    let eta = env.block.time + config.timelock_delay;
+    queue.actions.push(QueuedAction {
+        id,
+        action,
+        queued_by: env.message.sender,
+        eta,
+    });
+    queue.next_id += 1;
+    store_action_queue(&mut deps.storage, &queue)?;
 
     Ok(HandleResponse {
         messages: vec![],
-        log: vec![],
-        data: Some(to_binary(&HandleAnswer::ChangeLotteryDuration { status: Success })?),
+        log: vec![log("queued_action", id), log("eta", eta)],
+        data: Some(to_binary(&HandleAnswer::QueueAction { status: Success, id, eta })?),
     })
 }
 
-fn change_triggerer_share<S: Storage, A: Api, Q: Querier>(
+fn execute_action<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    percentage: u64,
+    id: u64,
 ) -> StdResult<HandleResponse> {
-    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
-    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
-    let mut config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
 
-    config.triggerer_share_percentage = percentage;
-    configstore.store(CONFIG_KEY, &config)?;
+    let mut queue = load_action_queue(&deps.storage)?;
+    let index = queue.actions.iter().position(|queued| queued.id == id)
+        .ok_or_else(|| StdError::generic_err("No queued action with this id"))?;
+    if env.block.time < queue.actions[index].eta {
+        return Err(StdError::generic_err(format!("This action can not be executed before {}", queue.actions[index].eta)));
+    }
+    //The ChangeStakingContractFlow runs on a stopped contract, the delay change and the migration at any time
+    let action = queue.actions.remove(index).action;
+    let needs_stop = match action {
+        TimelockedAction::ChangeTimelockDelay { .. } | TimelockedAction::MigrateStakingContract { .. } => false,
+        _ => true,
+    };
+    if needs_stop && !is_stopped(config.status) {
+        return Err(StdError::generic_err("This action can only be executed while the contract is stopped"));
+    }
+    store_action_queue(&mut deps.storage, &queue)?;
+
+    //The executor needs the role the action needs, checked again by the handler
+    match action {
+        TimelockedAction::ChangeStakingContract { address, contract_hash } => change_staking_contract(deps, env, address, contract_hash),
+        TimelockedAction::WithdrawExcess {} => withdraw_excess(deps, env),
+        TimelockedAction::EmergencyRedeemFromStaking {} => emergency_redeem_from_staking(deps, env),
+        TimelockedAction::ChangeTimelockDelay { delay } => change_timelock_delay(deps, env, delay),
+        TimelockedAction::MigrateStakingContract { new_contract } => migrate_staking_contract(deps, env, new_contract),
+    }
+}
+
+fn cancel_action<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+
+    let mut queue = load_action_queue(&deps.storage)?;
+    let index = queue.actions.iter().position(|queued| queued.id == id)
+        .ok_or_else(|| StdError::generic_err("No queued action with this id"))?;
+    queue.actions.remove(index);
+    store_action_queue(&mut deps.storage, &queue)?;
 
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::ChangeTriggererShare { status: Success })?),
+        data: Some(to_binary(&HandleAnswer::CancelAction { status: Success })?),
     })
 }
 
-pub fn change_staking_contract<S: Storage, A: Api, Q: Querier>(
+fn change_timelock_delay<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    address: HumanAddr,
-    contract_hash: String,
+    delay: u64,
 ) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
     let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
     let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
     let mut config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
 
-    config.staking_contract = SecretContract {
-        address,
-        contract_hash,
-    };
+    config.timelock_delay = delay;
     configstore.store(CONFIG_KEY, &config)?;
 
-    return Ok(HandleResponse {
+    Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::ChangeStakingContract {
-            status: Success,
-        })?),
-    });
+        data: Some(to_binary(&HandleAnswer::ChangeTimelockDelay { status: Success })?),
+    })
+}
+
+fn admin_action_role(action: &AdminAction) -> Role {
+    match action {
+        AdminAction::Queue { action } => action_role(action),
+        AdminAction::ChangeTriggererShare { .. }
+        | AdminAction::ChangePrizeTiers { .. }
+        | AdminAction::ChangePermissionlessDraws { .. }
+        | AdminAction::ChangeWeightCurve { .. }
+        | AdminAction::ChangeUnbondingPeriod { .. }
+        | AdminAction::ChangeEarlyExitFee { .. }
+        | AdminAction::ChangeDepositLimits { .. }
+        | AdminAction::ChangeLotteryDuration { .. } => Role::ParameterManager,
+        _ => Role::Owner,
+    }
+}
+
+fn validate_multisig(multisig: &Option<Multisig>) -> StdResult<()> {
+    if let Some(multisig) = multisig {
+        if multisig.signers.is_empty() || multisig.signers.len() > MAX_ROLE_MEMBERS {
+            return Err(StdError::generic_err(format!("A multisig has between 1 and {} signers", MAX_ROLE_MEMBERS)));
+        }
+        if multisig.threshold == 0 || multisig.threshold > multisig.signers.len() as u64 {
+            return Err(StdError::generic_err("Multisig threshold must be between 1 and the number of signers"));
+        }
+        for (i, signer) in multisig.signers.iter().enumerate() {
+            if multisig.signers[..i].contains(signer) {
+                return Err(StdError::generic_err("Multisig signers must be distinct"));
+            }
+        }
+    }
+    Ok(())
 }
 
-fn resume_contract<S: Storage, A: Api, Q: Querier>(
+//Runs the action right away without a multisig, otherwise opens a proposal for it
+fn submit_admin_action<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    action: AdminAction,
 ) -> StdResult<HandleResponse> {
-    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
-    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
-    let mut config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    if config.multisig.is_none() {
+        return run_admin_action(deps, env, action);
+    }
+    check_role(&deps.storage, admin_action_role(&action), &env.message.sender)?;
 
-    config.is_stopped = false;
-    config.is_stopped_can_withdraw = false;
-    configstore.store(CONFIG_KEY, &config)?;
+    let mut book = load_proposal_book(&deps.storage)?;
+    if book.proposals.len() >= MAX_OPEN_PROPOSALS {
+        return Err(StdError::generic_err(format!("At most {} proposals can be open", MAX_OPEN_PROPOSALS)));
+    }
+    let proposal_id = book.next_id;
+    book.proposals.push(Proposal {
+        id: proposal_id,
+        action,
+        proposer: env.message.sender,
+        approvals: vec![],
+    });
+    book.next_id += 1;
+    store_proposal_book(&mut deps.storage, &book)?;
 
-    return Ok(HandleResponse {
+    Ok(HandleResponse {
         messages: vec![],
-        log: vec![],
-        data: Some(to_binary(&HandleAnswer::ResumeContract { status: Success })?),
-    });
+        log: vec![log("proposal", proposal_id)],
+        data: Some(to_binary(&HandleAnswer::Propose { status: Success, proposal_id })?),
+    })
 }
 
-fn stop_contract<S: Storage, A: Api, Q: Querier>(
+fn run_admin_action<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    action: AdminAction,
 ) -> StdResult<HandleResponse> {
-    //Checking if admin
-    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
-    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
-    let mut config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+    match action {
+        AdminAction::Queue { action } => queue_action(deps, env, action),
+        AdminAction::ProposeAdmin { address } => propose_handover(deps, env, Role::Owner, address),
+        AdminAction::ProposeTriggerer { address } => propose_handover(deps, env, Role::Triggerer, address),
+        AdminAction::GrantRole { role, address } => grant_role(deps, env, role, address),
+        AdminAction::RevokeRole { role, address } => revoke_role(deps, env, role, address),
+        AdminAction::SetMultisig { multisig } => set_multisig(deps, env, multisig),
+        AdminAction::CancelAction { id } => cancel_action(deps, env, id),
+        AdminAction::ChangeFeeSchedule { fees } => change_fee_schedule(deps, env, fees),
+        AdminAction::SetContractStatus { status, reason } => set_contract_status(deps, env, status, reason),
+        AdminAction::ChangeTriggererShare { percentage } => change_triggerer_share(deps, env, percentage),
+        AdminAction::ChangePrizeTiers { tiers } => change_prize_tiers(deps, env, tiers),
+        AdminAction::ChangePermissionlessDraws { enabled, max_bounty } => change_permissionless_draws(deps, env, enabled, max_bounty),
+        AdminAction::ChangeWeightCurve { curve } => change_weight_curve(deps, env, curve),
+        AdminAction::ChangeUnbondingPeriod { period } => change_unbonding_period(deps, env, period),
+        AdminAction::ChangeEarlyExitFee { percentage, window } => change_early_exit_fee(deps, env, percentage, window),
+        AdminAction::ChangeDepositLimits { min_deposit, max_deposit_per_user, pool_cap } => {
+            change_deposit_limits(deps, env, min_deposit, max_deposit_per_user, pool_cap)
+        }
+        AdminAction::ChangeLotteryDuration { duration } => change_lottery_duration(deps, env, duration),
+    }
+}
+
+fn approve<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    proposal_id: u64,
+) -> StdResult<HandleResponse> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    let multisig = config.multisig.ok_or_else(|| StdError::generic_err("There is no multisig"))?;
+    if !multisig.signers.contains(&env.message.sender) {
+        return Err(StdError::generic_err("Only multisig signers can approve proposals"));
+    }
 
-    //Can't stop contract if already stopped
-    if !config.is_stopped {
-        config.is_stopped = true;
-        configstore.store(CONFIG_KEY, &config)?;
+    let mut book = load_proposal_book(&deps.storage)?;
+    let index = book.proposals.iter().position(|proposal| proposal.id == proposal_id)
+        .ok_or_else(|| StdError::generic_err("No open proposal with this id"))?;
+    if book.proposals[index].approvals.contains(&env.message.sender) {
+        return Err(StdError::generic_err("This signer has already approved the proposal"));
+    }
+    book.proposals[index].approvals.push(env.message.sender.clone());
+    let approvals = book.proposals[index].approvals.len() as u64;
 
+    if approvals < multisig.threshold {
+        store_proposal_book(&mut deps.storage, &book)?;
         return Ok(HandleResponse {
             messages: vec![],
             log: vec![],
-            data: Some(to_binary(&HandleAnswer::StopContract { status: Success })?),
+            data: Some(to_binary(&HandleAnswer::Approve { status: Success, approvals })?),
         });
-    } else {
-        return Err(StdError::generic_err(format!(
-            "Contract is already stopped."
-        )));
     }
+
+    //The proposal runs as if its proposer had sent the action
+    let proposal = book.proposals.remove(index);
+    store_proposal_book(&mut deps.storage, &book)?;
+    let mut proposer_env = env;
+    proposer_env.message.sender = proposal.proposer;
+    run_admin_action(deps, proposer_env, proposal.action)
+}
+
+fn cancel_proposal<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    proposal_id: u64,
+) -> StdResult<HandleResponse> {
+    let mut book = load_proposal_book(&deps.storage)?;
+    let index = book.proposals.iter().position(|proposal| proposal.id == proposal_id)
+        .ok_or_else(|| StdError::generic_err("No open proposal with this id"))?;
+    if book.proposals[index].proposer != env.message.sender {
+        return Err(StdError::generic_err("Only the proposer can cancel a proposal"));
+    }
+    book.proposals.remove(index);
+    store_proposal_book(&mut deps.storage, &book)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CancelProposal { status: Success })?),
+    })
 }
 
-fn allow_withdraw_when_stopped<S: Storage, A: Api, Q: Querier>(
+fn set_multisig<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    multisig: Option<Multisig>,
 ) -> StdResult<HandleResponse> {
-    //checking Admin
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    validate_multisig(&multisig)?;
+
+    //Approvals collected under the old signers no longer count
+    let mut book = load_proposal_book(&deps.storage)?;
+    book.proposals.clear();
+    store_proposal_book(&mut deps.storage, &book)?;
+
     let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
     let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
     let mut config: Config = configstore.load(CONFIG_KEY)?;
-    let _ = check_if_admin(&config, &env.message.sender);
-
-    //setting Config
-    config.is_stopped_can_withdraw = true;
+    config.multisig = multisig;
     configstore.store(CONFIG_KEY, &config)?;
 
     Ok(HandleResponse {
         messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::AllowWithdrawWhenStopped { status: Success })?),
+        data: Some(to_binary(&HandleAnswer::SetMultisig { status: Success })?),
     })
 }
 
-fn emergency_redeem_from_staking<S: Storage, A: Api, Q: Querier>(
+fn grant_role<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    role: Role,
+    address: HumanAddr,
 ) -> StdResult<HandleResponse> {
-    //Check if Admin
-    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
-    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
-    let config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    //A malformed address could never use the role
+    deps.api.canonical_address(&address)?;
+
+    let mut members = load_role_members(&deps.storage, role)?;
+    if !members.contains(&address) {
+        if members.len() >= MAX_ROLE_MEMBERS {
+            return Err(StdError::generic_err(format!("A role can be granted to at most {} addresses", MAX_ROLE_MEMBERS)));
+        }
+        members.push(address);
+        store_role_members(&mut deps.storage, role, &members)?;
+    }
 
-    //Querying Rewards
-    let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::GrantRole { status: Success })?),
+    })
+}
 
-    //Setting supply params
-    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
-    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
-    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
-    let amount_to_redeem = supply_pool.total_rewards_restaked + supply_pool.total_tokens_staked;
-    supply_pool.pending_staking_rewards += staking_rewards_response.rewards.rewards;
-    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
+fn revoke_role<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    role: Role,
+    address: HumanAddr,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
 
-    let mut messages: Vec<CosmosMsg> = vec![];
-    messages.push(
-        WasmMsg::Execute {
-            contract_addr: config.staking_contract.address,
-            callback_code_hash: config.staking_contract.contract_hash,
-            msg: to_binary(&LPStakingHandleMsg::Redeem {
-                amount: amount_to_redeem
-            })?,
-            send: vec![],
-        }
-            .into()
-    );
+    let mut members = load_role_members(&deps.storage, role)?;
+    if !members.contains(&address) {
+        return Err(StdError::generic_err("This address does not hold the role"));
+    }
+    //Without an owner no role could ever be granted again
+    if role == Role::Owner && members.len() == 1 {
+        return Err(StdError::generic_err("The last owner can not be revoked"));
+    }
+    members.retain(|member| member != &address);
+    store_role_members(&mut deps.storage, role, &members)?;
 
     Ok(HandleResponse {
-        messages,
+        messages: vec![],
         log: vec![],
-        data: Some(to_binary(&HandleAnswer::EmergencyRedeemFromStaking {
-            status: Success,
-        })?),
+        data: Some(to_binary(&HandleAnswer::RevokeRole { status: Success })?),
     })
 }
 
-pub fn redelegate_to_contract<S: Storage, A: Api, Q: Querier>(
+fn change_lottery_duration<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
+    duration: u64,
 ) -> StdResult<HandleResponse> {
-    //Checking if Admin
-    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
-    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
-    let config: Config = configstore.load(CONFIG_KEY)?;
-    check_if_admin(&config, &env.message.sender)?;
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
 
-    //Querying rewards
-    let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
-    let mut sefi_pending_staking_rewards = Uint128(0);
-    if staking_rewards_response.rewards.rewards > Uint128(0) {
-        sefi_pending_staking_rewards = staking_rewards_response.rewards.rewards
+    if duration == 0 {
+        return Err(StdError::generic_err("Round duration must be greater than zero"));
     }
 
-    //Updating SupplyPool
-    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
-    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
-    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
-    let amount_to_restake = supply_pool.total_tokens_staked + supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+    //Applies from the next round on. With a schedule anchor the boundaries become anchor + k * duration.
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.duration = Some(duration);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeLotteryDuration { status: Success })?),
+    })
+}
+
+fn change_triggerer_share<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    percentage: u64,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    //The prize accruing now is shared at the current percentage. Changes the schedule already queued, if any.
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    let mut fees = pending_changes.fees.unwrap_or(config.fees);
+    fees.triggerer_fee_bps = percentage;
+    validate_fee_schedule(&fees)?;
+    pending_changes.fees = Some(fees);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeTriggererShare { status: Success })?),
+    })
+}
+
+fn change_prize_tiers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    tiers: Vec<u64>,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+
+    validate_prize_tiers(&tiers)?;
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.prize_tiers = Some(tiers);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangePrizeTiers { status: Success })?),
+    })
+}
+
+fn change_fee_schedule<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    fees: FeeSchedule,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+
+    validate_fee_schedule(&fees)?;
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.fees = Some(fees);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeFeeSchedule { status: Success })?),
+    })
+}
+
+fn change_weight_curve<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    curve: WeightCurve,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    if config.ticket_size.is_some() {
+        return Err(StdError::generic_err("Weight curves do not apply in ticket mode"));
+    }
+    validate_weight_curve(&curve)?;
+    //Takes effect when the next round starts
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.weight_curve = Some(curve);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeWeightCurve { status: Success })?),
+    })
+}
+
+fn change_unbonding_period<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    period: u64,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+
+    //Pending unbondings keep the release time they were created with
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.unbonding_period = Some(period);
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeUnbondingPeriod { status: Success })?),
+    })
+}
+
+fn change_early_exit_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    percentage: u64,
+    window: u64,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+
+    validate_early_exit_fee(percentage)?;
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.early_exit_fee = Some(EarlyExitFee { percentage, window });
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeEarlyExitFee { status: Success })?),
+    })
+}
+
+fn change_deposit_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    min_deposit: Uint128,
+    max_deposit_per_user: Option<Uint128>,
+    pool_cap: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+
+    //Lowering a cap below what is already delegated only stops new deposits
+    validate_deposit_limits(min_deposit, max_deposit_per_user)?;
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.deposit_limits = Some(DepositLimits { min_deposit, max_deposit_per_user, pool_cap });
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeDepositLimits { status: Success })?),
+    })
+}
+
+fn change_permissionless_draws<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    enabled: bool,
+    max_bounty: Uint128,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::ParameterManager, &env.message.sender)?;
+
+    //The current round is drawn by whoever was allowed to when the change was made
+    let mut pending_changes = load_pending_config_changes(&deps.storage)?;
+    pending_changes.permissionless_draws = Some(PermissionlessDraws { enabled, max_bounty });
+    store_pending_config_changes(&mut deps.storage, &pending_changes)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangePermissionlessDraws { status: Success })?),
+    })
+}
+
+pub fn change_staking_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    contract_hash: String,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
+    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
+    let mut config: Config = configstore.load(CONFIG_KEY)?;
+
+    config.staking_contract = SecretContract {
+        address,
+        contract_hash,
+    };
+    configstore.store(CONFIG_KEY, &config)?;
+
+    //RedelegateToNewContract and the rewards queries need the viewing key on the new contract
+    return Ok(HandleResponse {
+        messages: vec![
+            snip20::set_viewing_key_msg(
+                STAKING_VK.to_string(),
+                None,
+                RESPONSE_BLOCK_SIZE,
+                config.staking_contract.contract_hash,
+                config.staking_contract.address,
+            )?
+        ],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::ChangeStakingContract {
+            status: Success,
+        })?),
+    });
+}
+
+fn set_contract_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    status: ContractStatus,
+    reason: Option<String>,
+) -> StdResult<HandleResponse> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let mut config: Config = configstore.load(CONFIG_KEY)?;
+
+    //A Pauser can stop the contract further but only an owner can resume any part of it
+    if is_resume(config.status, status) {
+        check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    } else {
+        check_role(&deps.storage, Role::Pauser, &env.message.sender)?;
+    }
+    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
+    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
+
+    config.status = status;
+    config.status_reason = reason;
+    configstore.store(CONFIG_KEY, &config)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::SetContractStatus { status: Success })?),
+    })
+}
+
+fn emergency_redeem_from_staking<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    //Querying Rewards
+    let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
+
+    //Setting supply params
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    let amount_to_redeem = supply_pool.total_rewards_restaked + supply_pool.total_tokens_staked;
+    supply_pool.pending_staking_rewards += staking_rewards_response.rewards.rewards;
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    messages.push(
+        WasmMsg::Execute {
+            contract_addr: config.staking_contract.address,
+            callback_code_hash: config.staking_contract.contract_hash,
+            msg: to_binary(&LPStakingHandleMsg::Redeem {
+                amount: amount_to_redeem
+            })?,
+            send: vec![],
+        }
+            .into()
+    );
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::EmergencyRedeemFromStaking {
+            status: Success,
+        })?),
+    })
+}
+
+//Moves everything staked to new_contract in one transaction. The redeem, the viewing key and the deposit are
+//messages of this transaction, so if any of them fails the whole migration is reverted.
+fn migrate_staking_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    new_contract: SecretContract,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let mut config: Config = configstore.load(CONFIG_KEY)?;
+    if new_contract.address == config.staking_contract.address {
+        return Err(StdError::generic_err("The pool already stakes in this contract"));
+    }
+
+    //Rewards the old contract pays out on redeem stay in the pool for the next draw
+    let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
+
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    let amount_to_migrate = supply_pool.total_tokens_staked + supply_pool.total_rewards_restaked;
+    supply_pool.pending_staking_rewards += staking_rewards_response.rewards.rewards;
+    supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if amount_to_migrate > Uint128(0) {
+        messages.push(
+            WasmMsg::Execute {
+                contract_addr: config.staking_contract.address.clone(),
+                callback_code_hash: config.staking_contract.contract_hash.clone(),
+                msg: to_binary(&LPStakingHandleMsg::Redeem {
+                    amount: amount_to_migrate
+                })?,
+                send: vec![],
+            }
+                .into()
+        );
+    }
+    messages.push(snip20::set_viewing_key_msg(
+        STAKING_VK.to_string(),
+        None,
+        RESPONSE_BLOCK_SIZE,
+        new_contract.contract_hash.clone(),
+        new_contract.address.clone(),
+    )?);
+    if amount_to_migrate > Uint128(0) {
+        messages.push(send_msg(
+            new_contract.address.clone(),
+            amount_to_migrate,
+            Some(to_binary(&LPStakingHandleMsg::Deposit {})?),
+            None,
+            RESPONSE_BLOCK_SIZE,
+            config.token.contract_hash.clone(),
+            config.token.address.clone(),
+        )?);
+    }
+
+    let old_contract = config.staking_contract.address.clone();
+    config.staking_contract = new_contract.clone();
+    let mut config_prefixed = PrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &mut deps.storage);
+    let mut configstore = TypedStoreMut::<Config, PrefixedStorage<'_, S>>::attach(&mut config_prefixed);
+    configstore.store(CONFIG_KEY, &config)?;
+
+    let mut migration_history = PrefixedStorage::multilevel(&[MIGRATION_HISTORY_KEY], &mut deps.storage);
+    let mut migration_history_append = AppendStoreMut::attach_or_create(&mut migration_history)?;
+    migration_history_append.push(&StakingMigration {
+        old_contract,
+        new_contract: new_contract.address,
+        amount: amount_to_migrate,
+        time: env.block.time,
+    })?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("migrated_amount", amount_to_migrate)],
+        data: Some(to_binary(&HandleAnswer::MigrateStakingContract {
+            status: Success,
+            amount: amount_to_migrate,
+        })?),
+    })
+}
+
+pub fn redelegate_to_contract<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    check_role(&deps.storage, Role::Owner, &env.message.sender)?;
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    //Querying rewards
+    let staking_rewards_response: LPStakingRewardsResponse = query_pending_rewards(&deps, &env, &config)?;
+    let mut sefi_pending_staking_rewards = Uint128(0);
+    if staking_rewards_response.rewards.rewards > Uint128(0) {
+        sefi_pending_staking_rewards = staking_rewards_response.rewards.rewards
+    }
+
+    //Updating SupplyPool
+    let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut deps.storage);
+    let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, S>>::attach(&mut supply_pool_prefixed);
+    let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    let amount_to_restake = supply_pool.total_tokens_staked + supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
     supply_pool.total_rewards_restaked += supply_pool.pending_staking_rewards;
     supply_pool.pending_staking_rewards = sefi_pending_staking_rewards;
     supply_store.store(SUPPLY_POOL_KEY, &supply_pool)?;
@@ -1079,18 +2314,102 @@ pub fn redelegate_to_contract<S: Storage, A: Api, Q: Querier>(
     })
 }
 
-//HELPER FUNCTIONS
+//HELPER FUNCTIONS
+
+fn role_key(role: Role) -> &'static [u8] {
+    match role {
+        Role::Owner => b"owner",
+        Role::Pauser => b"pauser",
+        Role::ParameterManager => b"parameter_manager",
+        Role::FeeCollector => b"fee_collector",
+        Role::Triggerer => b"triggerer",
+    }
+}
+
+fn load_role_members<S: ReadonlyStorage>(storage: &S, role: Role) -> StdResult<Vec<HumanAddr>> {
+    let roles_prefixed = ReadonlyPrefixedStorage::multilevel(&[ROLES_KEY_PREFIX], storage);
+    let roles_store = TypedStore::<Vec<HumanAddr>, ReadonlyPrefixedStorage<'_, S>>::attach(&roles_prefixed);
+    Ok(roles_store.may_load(role_key(role))?.unwrap_or_default())
+}
+
+fn store_role_members<S: Storage>(storage: &mut S, role: Role, members: &Vec<HumanAddr>) -> StdResult<()> {
+    let mut roles_prefixed = PrefixedStorage::multilevel(&[ROLES_KEY_PREFIX], storage);
+    let mut roles_store = TypedStoreMut::<Vec<HumanAddr>, PrefixedStorage<'_, S>>::attach(&mut roles_prefixed);
+    roles_store.store(role_key(role), members)
+}
+
+fn load_pending_handover<S: ReadonlyStorage>(storage: &S) -> StdResult<PendingHandover> {
+    let handover_prefixed = ReadonlyPrefixedStorage::multilevel(&[PENDING_HANDOVER_KEY_PREFIX], storage);
+    let handover_store = TypedStore::<PendingHandover, ReadonlyPrefixedStorage<'_, S>>::attach(&handover_prefixed);
+    Ok(handover_store.may_load(PENDING_HANDOVER_KEY)?.unwrap_or_default())
+}
+
+fn store_pending_handover<S: Storage>(storage: &mut S, handover: &PendingHandover) -> StdResult<()> {
+    let mut handover_prefixed = PrefixedStorage::multilevel(&[PENDING_HANDOVER_KEY_PREFIX], storage);
+    let mut handover_store = TypedStoreMut::<PendingHandover, PrefixedStorage<'_, S>>::attach(&mut handover_prefixed);
+    handover_store.store(PENDING_HANDOVER_KEY, handover)
+}
+
+fn load_action_queue<S: ReadonlyStorage>(storage: &S) -> StdResult<ActionQueue> {
+    let queue_prefixed = ReadonlyPrefixedStorage::multilevel(&[ACTION_QUEUE_KEY_PREFIX], storage);
+    let queue_store = TypedStore::<ActionQueue, ReadonlyPrefixedStorage<'_, S>>::attach(&queue_prefixed);
+    Ok(queue_store.may_load(ACTION_QUEUE_KEY)?.unwrap_or_default())
+}
+
+fn store_action_queue<S: Storage>(storage: &mut S, queue: &ActionQueue) -> StdResult<()> {
+    let mut queue_prefixed = PrefixedStorage::multilevel(&[ACTION_QUEUE_KEY_PREFIX], storage);
+    let mut queue_store = TypedStoreMut::<ActionQueue, PrefixedStorage<'_, S>>::attach(&mut queue_prefixed);
+    queue_store.store(ACTION_QUEUE_KEY, queue)
+}
+
+fn load_proposal_book<S: ReadonlyStorage>(storage: &S) -> StdResult<ProposalBook> {
+    let proposals_prefixed = ReadonlyPrefixedStorage::multilevel(&[PROPOSALS_KEY_PREFIX], storage);
+    let proposals_store = TypedStore::<ProposalBook, ReadonlyPrefixedStorage<'_, S>>::attach(&proposals_prefixed);
+    Ok(proposals_store.may_load(PROPOSALS_KEY)?.unwrap_or_default())
+}
 
-fn is_admin(config: &Config, account: &HumanAddr) -> StdResult<bool> {
-    if &config.admin != account {
-        return Ok(false);
-    }
+fn store_proposal_book<S: Storage>(storage: &mut S, book: &ProposalBook) -> StdResult<()> {
+    let mut proposals_prefixed = PrefixedStorage::multilevel(&[PROPOSALS_KEY_PREFIX], storage);
+    let mut proposals_store = TypedStoreMut::<ProposalBook, PrefixedStorage<'_, S>>::attach(&mut proposals_prefixed);
+    proposals_store.store(PROPOSALS_KEY, book)
+}
+
+fn load_user_totals<S: ReadonlyStorage>(storage: &S) -> StdResult<UserTotals> {
+    let totals_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_TOTALS_KEY_PREFIX], storage);
+    let totals_store = TypedStore::<UserTotals, ReadonlyPrefixedStorage<'_, S>>::attach(&totals_prefixed);
+    Ok(totals_store.may_load(USER_TOTALS_KEY)?.unwrap_or_default())
+}
+
+fn store_user_totals<S: Storage>(storage: &mut S, totals: &UserTotals) -> StdResult<()> {
+    let mut totals_prefixed = PrefixedStorage::multilevel(&[USER_TOTALS_KEY_PREFIX], storage);
+    let mut totals_store = TypedStoreMut::<UserTotals, PrefixedStorage<'_, S>>::attach(&mut totals_prefixed);
+    totals_store.store(USER_TOTALS_KEY, totals)
+}
+
+/// track_user_totals moves the user totals by the change made to one user, from `before` to `after`
+fn track_user_totals<S: Storage>(storage: &mut S, before: &UserInfo, after: &UserInfo) -> StdResult<()> {
+    let mut totals = load_user_totals(storage)?;
+    totals.principal = ((totals.principal + after.amount_delegated) - before.amount_delegated)?;
+    totals.pending_withdrawals = ((totals.pending_withdrawals + pending_withdrawals(after)) - pending_withdrawals(before))?;
+    store_user_totals(storage, &totals)
+}
+
+/// pending_withdrawals is what the contract holds for the user: available tokens and unbondings
+fn pending_withdrawals(user: &UserInfo) -> Uint128 {
+    user.unbondings.iter().fold(user.available_tokens_for_withdraw, |total, record| total + record.amount)
+}
 
-    Ok(true)
+fn has_role<S: ReadonlyStorage>(storage: &S, role: Role, account: &HumanAddr) -> StdResult<bool> {
+    Ok(load_role_members(storage, role)?.contains(account))
 }
 
-fn check_if_admin(config: &Config, account: &HumanAddr) -> StdResult<()> {
-    if !is_admin(config, account)? {
+fn check_role<S: ReadonlyStorage>(storage: &S, role: Role, account: &HumanAddr) -> StdResult<()> {
+    if !has_role(storage, role, account)? {
+        if role == Role::Triggerer {
+            return Err(StdError::generic_err(
+                "This is an admin command. Admin commands can only be run from admin address and triggerer address",
+            ));
+        }
         return Err(StdError::generic_err(
             "This is an admin command. Admin commands can only be run from admin address",
         ));
@@ -1099,19 +2418,374 @@ fn check_if_admin(config: &Config, account: &HumanAddr) -> StdResult<()> {
     Ok(())
 }
 
-fn is_triggerer(config: &Config, account: &HumanAddr) -> StdResult<bool> {
-    if &config.triggerer != account {
-        return Ok(false);
+/// load_pending_draw_commitment returns the committed draw unless it was never revealed in time
+fn load_pending_draw_commitment<S: ReadonlyStorage>(storage: &S, config: &Config, current_height: u64) -> Option<DrawCommitment> {
+    let commitment = load_draw_commitment(storage).ok()??;
+    if current_height > commitment.commit_height + config.draw_reveal_delay + DRAW_REVEAL_WINDOW {
+        return None;
     }
-    Ok(true)
+
+    Some(commitment)
 }
 
-fn check_if_triggerer(config: &Config, account: &HumanAddr) -> StdResult<()> {
-    if !is_triggerer(config, account)? {
-        return Err(StdError::generic_err(
-            "This is an admin command. Admin commands can only be run from admin address and triggerer address",
-        ));
+/// entries_frozen tells whether a pending draw keeps every entry from changing, whoever committed it.
+/// A committer knows the entropy, moving any balance in or out would let them steer the winner.
+/// A commitment that is never revealed only locks the entries until it expires after the reveal window.
+fn entries_frozen<S: ReadonlyStorage>(storage: &S, config: &Config, current_height: u64) -> bool {
+    load_pending_draw_commitment(storage, config, current_height).is_some()
+}
+
+/// load_draw_commitment returns the last commitment that was not revealed, expired or not
+fn load_draw_commitment<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<DrawCommitment>> {
+    let commitment_prefixed = ReadonlyPrefixedStorage::multilevel(&[DRAW_COMMITMENT_KEY_PREFIX], storage);
+    let commitment_store = TypedStore::<DrawCommitment, ReadonlyPrefixedStorage<'_, S>>::attach(&commitment_prefixed);
+    commitment_store.may_load(DRAW_COMMITMENT_KEY)
+}
+
+fn load_lapsed_committers<S: ReadonlyStorage>(storage: &S) -> StdResult<LapsedCommitters> {
+    let commitment_prefixed = ReadonlyPrefixedStorage::multilevel(&[DRAW_COMMITMENT_KEY_PREFIX], storage);
+    let lapsed_store = TypedStore::<LapsedCommitters, ReadonlyPrefixedStorage<'_, S>>::attach(&commitment_prefixed);
+    Ok(lapsed_store.may_load(LAPSED_COMMITTERS_KEY)?.unwrap_or_default())
+}
+
+fn store_lapsed_committers<S: Storage>(storage: &mut S, lapsed: &LapsedCommitters) -> StdResult<()> {
+    let mut commitment_prefixed = PrefixedStorage::multilevel(&[DRAW_COMMITMENT_KEY_PREFIX], storage);
+    let mut lapsed_store = TypedStoreMut::<LapsedCommitters, PrefixedStorage<'_, S>>::attach(&mut commitment_prefixed);
+    lapsed_store.store(LAPSED_COMMITTERS_KEY, lapsed)
+}
+
+/// update_user_entry keeps the draw index and the user's round entry in line with a change to their delegated amount.
+/// The entry tree holds every user's full weight, their delegated amount or their tickets in ticket mode.
+/// The part of this round's deposits that does not count in the round, by the weight curve or, in ticket mode,
+/// tickets bought after the round ended, is also added to the round's deficit tree, and a user's weight in the
+/// draw is their entry minus their deficit.
+/// The caller stores the updated user info.
+fn update_user_entry<S: Storage>(
+    storage: &mut S,
+    config: &Config,
+    address: &HumanAddr,
+    user: &mut UserInfo,
+    delegated_before: Uint128,
+    time: u64,
+) -> StdResult<()> {
+    let delegated_after = user.amount_delegated;
+    let (entry_before, entry_after) = match config.ticket_size {
+        Some(ticket_size) => (delegated_before.0 / ticket_size.0, delegated_after.0 / ticket_size.0),
+        None => (delegated_before.0, delegated_after.0),
+    };
+    if entry_before == entry_after {
+        return Ok(());
+    }
+
+    let existing_slot = load_user_slot(storage, address);
+    let mut slot_registry_prefixed = PrefixedStorage::multilevel(&[SLOT_REGISTRY_KEY_PREFIX], storage);
+    let mut slot_registry_store = TypedStoreMut::<SlotRegistry, PrefixedStorage<'_, S>>::attach(&mut slot_registry_prefixed);
+    let mut slot_registry: SlotRegistry = slot_registry_store.load(SLOT_REGISTRY_KEY)?;
+    let slot = existing_slot.unwrap_or(slot_registry.next_slot);
+    if existing_slot.is_none() {
+        if slot > fenwick::FENWICK_TREE_SIZE {
+            return Err(StdError::generic_err("The draw index has no free slots left"));
+        }
+        slot_registry.next_slot += 1;
+    }
+    if entry_before == 0 {
+        slot_registry.holders += 1;
+    } else if entry_after == 0 {
+        slot_registry.holders -= 1;
+    }
+    slot_registry_store.store(SLOT_REGISTRY_KEY, &slot_registry)?;
+
+    if existing_slot.is_none() {
+        let mut user_slot_prefixed = PrefixedStorage::multilevel(&[USER_SLOT_KEY_PREFIX], storage);
+        let mut user_slot_store = TypedStoreMut::<u64, PrefixedStorage<'_, S>>::attach(&mut user_slot_prefixed);
+        user_slot_store.store(address.0.as_bytes(), &slot)?;
+
+        let mut slot_owner_prefixed = PrefixedStorage::multilevel(&[SLOT_OWNER_KEY_PREFIX], storage);
+        let mut slot_owner_store = TypedStoreMut::<HumanAddr, PrefixedStorage<'_, S>>::attach(&mut slot_owner_prefixed);
+        slot_owner_store.store(&slot.to_be_bytes(), address)?;
+    }
+
+    let mut entry_tree = PrefixedStorage::multilevel(&[ENTRY_TREE_KEY_PREFIX], storage);
+    if entry_after > entry_before {
+        fenwick::add(&mut entry_tree, slot, entry_after - entry_before)?;
+    } else {
+        fenwick::sub(&mut entry_tree, slot, entry_before - entry_after)?;
+    }
+
+    //Square-root weights are kept up to date under every curve, so that the curve can change at any round
+    let (sqrt_before, sqrt_after) = (sqrt_weight(delegated_before.0), sqrt_weight(delegated_after.0));
+    if config.ticket_size.is_none() {
+        let mut sqrt_entry_tree = PrefixedStorage::multilevel(&[SQRT_ENTRY_TREE_KEY_PREFIX], storage);
+        if sqrt_after > sqrt_before {
+            fenwick::add(&mut sqrt_entry_tree, slot, sqrt_after - sqrt_before)?;
+        } else if sqrt_after < sqrt_before {
+            fenwick::sub(&mut sqrt_entry_tree, slot, sqrt_before - sqrt_after)?;
+        }
+    }
+
+    let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], storage);
+    let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
+    let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
+
+    //Compaction: an entry of an earlier round is merged into the carried over amount at full weight
+    if user.round_entry.round_id != a_lottery.round_id {
+        user.round_entry = RoundEntry { round_id: a_lottery.round_id, amount: Uint128(0), deficit: Uint128(0) };
+        let mut deficit_slots = load_deficit_slots(storage, a_lottery.round_id)?;
+        deficit_slots.push(slot);
+        store_deficit_slots(storage, a_lottery.round_id, &deficit_slots)?;
+    }
+    let round_entry = &mut user.round_entry;
+    let deficit_before = round_entry.deficit;
+    let (entry_before, entry_after) = (Uint128(entry_before), Uint128(entry_after));
+    if entry_after > entry_before {
+        let deposited = (entry_after - entry_before)?;
+        round_entry.amount += deposited;
+        //Tickets count in full, unless they were bought once the round ended
+        let weight = match config.ticket_size {
+            Some(_) if time >= a_lottery.end_time => Uint128(0),
+            Some(_) => deposited,
+            None => curve_weight(deposited, time, &a_lottery),
+        };
+        round_entry.deficit += (deposited - weight)?;
+    } else {
+        //Withdrawals are taken from the amount carried over from earlier rounds first, as the oldest entries
+        let withdrawn = (entry_before - entry_after)?;
+        let carried_over = (entry_before - round_entry.amount)?;
+        if withdrawn > carried_over {
+            let from_round = (withdrawn - carried_over)?;
+            let deficit_removed = round_entry.deficit.multiply_ratio(from_round.0, round_entry.amount.0);
+            round_entry.amount = (round_entry.amount - from_round)?;
+            round_entry.deficit = (round_entry.deficit - deficit_removed)?;
+        }
+    }
+
+    let deficit_after = round_entry.deficit;
+
+    let mut deficit_tree = PrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &a_lottery.round_id.to_be_bytes()], storage);
+    if deficit_after > deficit_before {
+        fenwick::add(&mut deficit_tree, slot, (deficit_after - deficit_before)?.0)?;
+    } else if deficit_after < deficit_before {
+        fenwick::sub(&mut deficit_tree, slot, (deficit_before - deficit_after)?.0)?;
+    }
+
+    //The square-root deficit is what the user's deficit takes off the square root of their amount
+    if config.ticket_size.is_none() && a_lottery.weight_curve == WeightCurve::SquareRoot {
+        let sqrt_deficit_before = sqrt_before - sqrt_weight(delegated_before.0 - deficit_before.0);
+        let sqrt_deficit_after = sqrt_after - sqrt_weight(delegated_after.0 - deficit_after.0);
+        let mut sqrt_deficit_tree = PrefixedStorage::multilevel(&[SQRT_DEFICIT_TREE_KEY_PREFIX, &a_lottery.round_id.to_be_bytes()], storage);
+        if sqrt_deficit_after > sqrt_deficit_before {
+            fenwick::add(&mut sqrt_deficit_tree, slot, sqrt_deficit_after - sqrt_deficit_before)?;
+        } else if sqrt_deficit_after < sqrt_deficit_before {
+            fenwick::sub(&mut sqrt_deficit_tree, slot, sqrt_deficit_before - sqrt_deficit_after)?;
+        }
+    }
+    Ok(())
+}
+
+fn load_deficit_slots<S: ReadonlyStorage>(storage: &S, round_id: u64) -> StdResult<Vec<u64>> {
+    let deficit_slots_prefixed = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_SLOTS_KEY_PREFIX], storage);
+    let deficit_slots_store = TypedStore::<Vec<u64>, ReadonlyPrefixedStorage<'_, S>>::attach(&deficit_slots_prefixed);
+    Ok(deficit_slots_store.may_load(&round_id.to_be_bytes())?.unwrap_or_default())
+}
+
+fn store_deficit_slots<S: Storage>(storage: &mut S, round_id: u64, deficit_slots: &[u64]) -> StdResult<()> {
+    let mut deficit_slots_prefixed = PrefixedStorage::multilevel(&[DEFICIT_SLOTS_KEY_PREFIX], storage);
+    let mut deficit_slots_store = TypedStoreMut::<Vec<u64>, PrefixedStorage<'_, S>>::attach(&mut deficit_slots_prefixed);
+    deficit_slots_store.store(&round_id.to_be_bytes(), &deficit_slots.to_vec())
+}
+
+/// clear_round_deficits removes a drawn round's deficit trees, which no entry refers to anymore.
+/// Only the slots given a round entry during the round can have a deficit in it.
+fn clear_round_deficits<S: Storage>(storage: &mut S, round_id: u64) -> StdResult<()> {
+    let deficit_slots = load_deficit_slots(storage, round_id)?;
+    for deficit_tree_prefix in [DEFICIT_TREE_KEY_PREFIX, SQRT_DEFICIT_TREE_KEY_PREFIX].iter() {
+        let mut deficit_tree = PrefixedStorage::multilevel(&[*deficit_tree_prefix, &round_id.to_be_bytes()], storage);
+        for slot in &deficit_slots {
+            fenwick::clear_slot(&mut deficit_tree, *slot)?;
+        }
+    }
+    let mut deficit_slots_prefixed = PrefixedStorage::multilevel(&[DEFICIT_SLOTS_KEY_PREFIX], storage);
+    deficit_slots_prefixed.remove(&round_id.to_be_bytes());
+    Ok(())
+}
+
+/// early_exit_fee is the fee on the part of a withdrawal taken from the user's recent deposits, if still inside the window
+fn early_exit_fee(config: &Config, user: &UserInfo, withdraw_amount: Uint128, time: u64) -> Uint128 {
+    if time >= user.recent_deposit.entry_time + config.early_exit_window {
+        return Uint128(0);
+    }
+    let carried_over = (user.amount_delegated - user.recent_deposit.amount).unwrap_or(Uint128(0));
+    match withdraw_amount - carried_over {
+        Ok(from_recent) => from_recent.multiply_ratio(config.early_exit_fee_percentage, 10000u64),
+        Err(_) => Uint128(0),
+    }
+}
+
+fn validate_deposit_limits(min_deposit: Uint128, max_deposit_per_user: Option<Uint128>) -> StdResult<()> {
+    if let Some(max_deposit_per_user) = max_deposit_per_user {
+        if max_deposit_per_user < min_deposit {
+            return Err(StdError::generic_err("Per-address limit can not be below the minimum deposit"));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_early_exit_fee(percentage: u64) -> StdResult<()> {
+    if percentage > 10000 {
+        return Err(StdError::generic_err("Early exit fee can not be more than 100 percent"));
+    }
+
+    Ok(())
+}
+
+/// release_unbondings moves every unbonding released by `time` to the user's available tokens
+fn release_unbondings(user: &mut UserInfo, time: u64) {
+    let (released, pending): (Vec<UnbondingRecord>, Vec<UnbondingRecord>) =
+        user.unbondings.drain(..).partition(|record| record.release_time <= time);
+    for record in released {
+        user.available_tokens_for_withdraw += record.amount;
+    }
+    user.unbondings = pending;
+}
+
+/// curve_weight is the part of a deposit made at `time` that counts in the current round's draw under the round's curve.
+/// Nothing deposited once the round has ended counts.
+fn curve_weight(amount: Uint128, time: u64, a_lottery: &Lottery) -> Uint128 {
+    if time >= a_lottery.end_time || a_lottery.duration == 0 {
+        return Uint128(0);
+    }
+    let time_left = (a_lottery.end_time - time).min(a_lottery.duration);
+    match a_lottery.weight_curve {
+        WeightCurve::Linear | WeightCurve::SquareRoot => amount.multiply_ratio(time_left, a_lottery.duration),
+        WeightCurve::Step { cutoff_percentage } => {
+            let time_elapsed = (a_lottery.duration - time_left) as u128;
+            if time_elapsed * 100 < (a_lottery.duration as u128) * (cutoff_percentage as u128) {
+                amount
+            } else {
+                Uint128(0)
+            }
+        }
+        WeightCurve::Flat => amount,
+    }
+}
+
+/// sqrt_weight is the square-root curve's weight for an amount, in fixed point
+fn sqrt_weight(amount: u128) -> u128 {
+    isqrt(amount.saturating_mul(SQRT_WEIGHT_SCALE))
+}
+
+/// draw_index_prefixes returns the entry and deficit trees a round is drawn from
+fn draw_index_prefixes(config: &Config, weight_curve: &WeightCurve) -> (&'static [u8], &'static [u8]) {
+    if config.ticket_size.is_none() && *weight_curve == WeightCurve::SquareRoot {
+        (SQRT_ENTRY_TREE_KEY_PREFIX, SQRT_DEFICIT_TREE_KEY_PREFIX)
+    } else {
+        (ENTRY_TREE_KEY_PREFIX, DEFICIT_TREE_KEY_PREFIX)
+    }
+}
+
+fn load_user_slot<S: ReadonlyStorage>(storage: &S, user: &HumanAddr) -> Option<u64> {
+    let user_slot_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_SLOT_KEY_PREFIX], storage);
+    let user_slot_store = TypedStore::<u64, ReadonlyPrefixedStorage<'_, S>>::attach(&user_slot_prefixed);
+    user_slot_store.load(user.0.as_bytes()).ok()
+}
+
+fn load_slot_owner<S: ReadonlyStorage>(storage: &S, slot: u64) -> StdResult<HumanAddr> {
+    let slot_owner_prefixed = ReadonlyPrefixedStorage::multilevel(&[SLOT_OWNER_KEY_PREFIX], storage);
+    let slot_owner_store = TypedStore::<HumanAddr, ReadonlyPrefixedStorage<'_, S>>::attach(&slot_owner_prefixed);
+    slot_owner_store.load(&slot.to_be_bytes())
+}
+
+/// weight_range returns the first and last positions of a slot's weight in the draw index, first > last when it has none.
+/// In ticket mode these are the slot's ticket numbers.
+fn weight_range<A: ReadonlyStorage, B: ReadonlyStorage>(entry_tree: &A, deficit_tree: &B, slot: u64) -> StdResult<(u128, u128)> {
+    let before = fenwick::net_prefix_sum(entry_tree, deficit_tree, slot - 1)?;
+    let through = fenwick::net_prefix_sum(entry_tree, deficit_tree, slot)?;
+    Ok((before + 1, through))
+}
+
+/// draw_winners picks up to `count` distinct winners, each with a chance proportional to their weight in the round.
+/// Every draw is a position in the cumulative weight index, found with logarithmically many reads. Once a user
+/// has won, their range is skipped by mapping later positions over the remaining weight only.
+fn draw_winners<S: ReadonlyStorage>(
+    storage: &S,
+    entry_tree_prefix: &[u8],
+    deficit_tree_prefix: &[u8],
+    round_id: u64,
+    rng: &mut ChaChaRng,
+    count: usize,
+) -> StdResult<Vec<(HumanAddr, u128)>> {
+    let entry_tree = ReadonlyPrefixedStorage::multilevel(&[entry_tree_prefix], storage);
+    let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[deficit_tree_prefix, &round_id.to_be_bytes()], storage);
+    let total_weight = fenwick::net_total(&entry_tree, &deficit_tree)?;
+
+    //(first position, weight) of every winner so far, ordered by first position
+    let mut excluded: Vec<(u128, u128)> = vec![];
+    let mut excluded_weight = 0u128;
+    let mut winners: Vec<(HumanAddr, u128)> = vec![];
+    while winners.len() < count && excluded_weight < total_weight {
+        let mut position = rng.gen_range(1, total_weight - excluded_weight + 1);
+        for (first, weight) in &excluded {
+            if *first <= position {
+                position += weight;
+            } else {
+                break;
+            }
+        }
+
+        let slot = fenwick::find_net(&entry_tree, &deficit_tree, position)?;
+        let (first, last) = weight_range(&entry_tree, &deficit_tree, slot)?;
+        winners.push((load_slot_owner(storage, slot)?, position));
+
+        let index = excluded.iter().position(|(start, _)| *start > first).unwrap_or(excluded.len());
+        excluded.insert(index, (first, last + 1 - first));
+        excluded_weight += last + 1 - first;
+    }
+
+    Ok(winners)
+}
+
+fn validate_fee_schedule(fees: &FeeSchedule) -> StdResult<()> {
+    if fees.triggerer_fee_bps > MAX_TRIGGERER_FEE_BPS {
+        return Err(StdError::generic_err(format!("Triggerer fee can be at most {} basis points", MAX_TRIGGERER_FEE_BPS)));
+    }
+    if fees.protocol_fee_bps > MAX_PROTOCOL_FEE_BPS {
+        return Err(StdError::generic_err(format!("Protocol fee can be at most {} basis points", MAX_PROTOCOL_FEE_BPS)));
+    }
+    if fees.charity_fee_bps > MAX_CHARITY_FEE_BPS {
+        return Err(StdError::generic_err(format!("Charity fee can be at most {} basis points", MAX_CHARITY_FEE_BPS)));
+    }
+    if fees.triggerer_fee_bps + fees.protocol_fee_bps + fees.charity_fee_bps > MAX_TOTAL_FEE_BPS {
+        return Err(StdError::generic_err(format!("Fees can be at most {} basis points in total", MAX_TOTAL_FEE_BPS)));
+    }
+    if fees.charity_fee_bps > 0 && fees.charity.is_none() {
+        return Err(StdError::generic_err("A charity fee needs a charity address"));
+    }
+
+    Ok(())
+}
+
+fn validate_weight_curve(weight_curve: &WeightCurve) -> StdResult<()> {
+    if let WeightCurve::Step { cutoff_percentage } = weight_curve {
+        if *cutoff_percentage == 0 || *cutoff_percentage > 100 {
+            return Err(StdError::generic_err("Step cut-off must be between 1 and 100 percent"));
+        }
+    }
+
+    Ok(())
+}
+
+/// validate_prize_tiers returns an error unless the tiers are non-zero percentages summing to 100
+fn validate_prize_tiers(tiers: &[u64]) -> StdResult<()> {
+    if tiers.is_empty() || tiers.len() > MAX_PRIZE_TIERS {
+        return Err(StdError::generic_err(format!(
+            "Number of prize tiers must be between 1 and {}", MAX_PRIZE_TIERS
+        )));
+    }
+    if tiers.iter().any(|tier| *tier == 0) || tiers.iter().try_fold(0u64, |sum, tier| sum.checked_add(*tier)) != Some(100) {
+        return Err(StdError::generic_err("Prize tiers must be non-zero percentages adding up to 100"));
     }
+
     Ok(())
 }
 
@@ -1124,6 +2798,15 @@ fn validate_start_time(start_time: u64, current_time: u64) -> StdResult<()> {
     }
 }
 
+/// next_boundary is the first time after `time` on the schedule anchor + k * duration
+fn next_boundary(anchor: u64, duration: u64, time: u64) -> u64 {
+    if time < anchor {
+        anchor - ((anchor - time - 1) / duration) * duration
+    } else {
+        anchor + ((time - anchor) / duration + 1) * duration
+    }
+}
+
 /// validate_end_height returns an error if the lottery ends in the future
 fn validate_end_time(end_time: u64, current_time: u64) -> StdResult<()> {
     if current_time < end_time {
@@ -1148,6 +2831,19 @@ fn query_pending_rewards<S: Storage, A: Api, Q: Querier>(
     Ok(staking_rewards_response)
 }
 
+/// query_staked_balance returns what the staking contract actually holds for this contract
+fn query_staked_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    config: &Config,
+) -> StdResult<Uint128> {
+    let staked: LPStakingBalanceResponse = LPStakingQueryMsg::Balance {
+        address: config.own_addr.clone(),
+        key: STAKING_VK.to_string(),
+    }.query(&deps.querier, config.staking_contract.contract_hash.clone(), config.staking_contract.address.clone())?;
+
+    Ok(staked.balance.amount)
+}
+
 fn query_total_rewards<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, height: Uint128) -> StdResult<Binary> {
     //Getting the pending_rewards
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
@@ -1165,22 +2861,309 @@ fn query_total_rewards<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, h
     let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
     let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
 
-    let total_rewards = rewards_in_lp_contract + supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+    let total_rewards = rewards_in_lp_contract + supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+
+    to_binary(&QueryAnswer::TotalRewards {
+        rewards: total_rewards,
+    })
+}
+
+fn query_total_deposit<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    //Getting the pending_rewards
+
+    let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
+    let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
+    let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+
+    to_binary(&QueryAnswer::TotalDeposits {
+        deposits: supply_pool.total_tokens_staked,
+    })
+}
+
+fn query_jackpot<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
+    let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
+    let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+
+    to_binary(&QueryAnswer::Jackpot {
+        rolled_over_prize: supply_pool.rolled_over_prize,
+        rolled_over_rounds: supply_pool.rolled_over_rounds,
+    })
+}
+
+fn query_round_info<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, round_id: u64) -> StdResult<Binary> {
+    let round_prefixed = ReadonlyPrefixedStorage::multilevel(&[ROUND_KEY_PREFIX], &deps.storage);
+    let round_store = TypedStore::<RoundRecord, ReadonlyPrefixedStorage<'_, S>>::attach(&round_prefixed);
+    let round = round_store.load(&round_id.to_be_bytes())
+        .map_err(|_| StdError::generic_err(format!("Round {} has not been drawn yet", round_id)))?;
+
+    to_binary(&QueryAnswer::RoundInfo { round })
+}
+
+fn query_pending_config_changes<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &deps.storage);
+    let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
+    let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
+
+    to_binary(&QueryAnswer::PendingConfigChanges {
+        changes: load_pending_config_changes(&deps.storage)?,
+        round_id: a_lottery.round_id,
+        draw_time: a_lottery.end_time,
+    })
+}
+
+fn query_roles<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let mut roles = vec![];
+    for role in [Role::Owner, Role::Pauser, Role::ParameterManager, Role::FeeCollector, Role::Triggerer].iter() {
+        roles.push((*role, load_role_members(&deps.storage, *role)?));
+    }
+
+    to_binary(&QueryAnswer::Roles {
+        roles,
+        pending: load_pending_handover(&deps.storage)?,
+    })
+}
+
+fn query_queued_actions<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    to_binary(&QueryAnswer::QueuedActions {
+        actions: load_action_queue(&deps.storage)?.actions,
+        delay: config.timelock_delay,
+    })
+}
+
+fn query_proposals<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    to_binary(&QueryAnswer::Proposals {
+        proposals: load_proposal_book(&deps.storage)?.proposals,
+        multisig: config.multisig,
+    })
+}
+
+fn query_contract_status<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    to_binary(&QueryAnswer::ContractStatus {
+        status: config.status,
+        reason: config.status_reason,
+    })
+}
+
+//Proxies the SNIP-20 token_info of the token the pool stakes
+fn query_token_info<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    let token_info = snip20::token_info_query(
+        &deps.querier,
+        RESPONSE_BLOCK_SIZE,
+        config.token.contract_hash,
+        config.token.address,
+    )?;
+
+    to_binary(&QueryAnswer::TokenInfo {
+        name: token_info.name,
+        symbol: token_info.symbol,
+        decimals: token_info.decimals,
+        total_supply: token_info.total_supply,
+    })
+}
+
+fn query_incentivized_token<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    to_binary(&QueryAnswer::IncentivizedToken { token: config.token })
+}
+
+fn query_config<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    //viewing_key and prng_seed are left out on purpose
+    to_binary(&QueryAnswer::Config {
+        version: CONTRACT_VERSION.to_string(),
+        admins: load_role_members(&deps.storage, Role::Owner)?,
+        triggerers: load_role_members(&deps.storage, Role::Triggerer)?,
+        pending_handover: load_pending_handover(&deps.storage)?,
+        fees: config.fees,
+        prize_tiers: config.prize_tiers,
+        token: config.token,
+        staking_contract: config.staking_contract,
+        own_addr: config.own_addr,
+        draw_reveal_delay: config.draw_reveal_delay,
+        permissionless_draws: config.permissionless_draws,
+        max_triggerer_bounty: config.max_triggerer_bounty,
+        ticket_size: config.ticket_size,
+        weight_curve: config.weight_curve,
+        unbonding_period: config.unbonding_period,
+        early_exit_fee_percentage: config.early_exit_fee_percentage,
+        early_exit_window: config.early_exit_window,
+        min_deposit: config.min_deposit,
+        max_deposit_per_user: config.max_deposit_per_user,
+        pool_cap: config.pool_cap,
+        timelock_delay: config.timelock_delay,
+        multisig: config.multisig,
+        status: config.status,
+        status_reason: config.status_reason,
+    })
+}
+
+fn query_staking_migrations<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let migration_history = ReadonlyPrefixedStorage::multilevel(&[MIGRATION_HISTORY_KEY], &deps.storage);
+    let mut migrations = vec![];
+    //The store only exists after the first migration
+    if let Some(Ok(migration_history_append)) = AppendStore::<'_, StakingMigration, ReadonlyPrefixedStorage<'_, S>>::attach(&migration_history) {
+        for migration in migration_history_append.iter() {
+            migrations.push(migration?);
+        }
+    }
+
+    to_binary(&QueryAnswer::StakingMigrations { migrations })
+}
+
+fn query_reconcile<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
+    let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
+    let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+
+    //What the contract holds, through the keys it registered at init
+    let token_balance = snip20::balance_query(
+        &deps.querier,
+        config.own_addr.clone(),
+        config.viewing_key.clone(),
+        RESPONSE_BLOCK_SIZE,
+        config.token.contract_hash.clone(),
+        config.token.address.clone(),
+    )?.amount;
+    let staked_balance = query_staked_balance(&deps, &config)?;
+
+    //What it owes. Rewards not yet paid out by the staking contract are on neither side.
+    let user_liabilities = supply_pool.total_tokens_staked + supply_pool.total_pending_withdrawals;
+    let fees = supply_pool.triggering_cost + supply_pool.protocol_fees + supply_pool.charity_fees;
+    let prize_pool = supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+
+    let assets = token_balance + staked_balance;
+    let liabilities = user_liabilities + fees + prize_pool;
+    let (surplus, shortfall) = if assets >= liabilities {
+        ((assets - liabilities)?, Uint128(0))
+    } else {
+        (Uint128(0), (liabilities - assets)?)
+    };
+
+    to_binary(&QueryAnswer::Reconcile {
+        token_balance,
+        staked_balance,
+        user_liabilities,
+        fees,
+        prize_pool,
+        surplus,
+        shortfall,
+    })
+}
+
+fn query_ticket_info<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+
+    let slot_registry_prefixed = ReadonlyPrefixedStorage::multilevel(&[SLOT_REGISTRY_KEY_PREFIX], &deps.storage);
+    let slot_registry_store = TypedStore::<SlotRegistry, ReadonlyPrefixedStorage<'_, S>>::attach(&slot_registry_prefixed);
+    let slot_registry: SlotRegistry = slot_registry_store.load(SLOT_REGISTRY_KEY)?;
+    let entry_tree = ReadonlyPrefixedStorage::multilevel(&[ENTRY_TREE_KEY_PREFIX], &deps.storage);
+    //Outside ticket mode the entry tree holds amounts, not tickets
+    let total_tickets = if config.ticket_size.is_some() { fenwick::total(&entry_tree) } else { 0 };
+
+    to_binary(&QueryAnswer::TicketInfo {
+        ticket_size: config.ticket_size,
+        total_tickets: Uint128(total_tickets),
+        holders: slot_registry.holders,
+    })
+}
+
+fn query_tickets<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, address: &HumanAddr) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
+    if config.ticket_size.is_none() {
+        return Err(StdError::generic_err("Ticket mode is not enabled for this pool"));
+    }
+
+    //Numbered as in the current round's draw, from the holder's place in the pool. The numbers are not fixed at purchase,
+    //they move whenever an earlier holder's tickets change, and tickets bought after the round ended only count from the next one.
+    let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &deps.storage);
+    let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, S>>::attach(&lottery_prefixed);
+    let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY)?;
+    let entry_tree = ReadonlyPrefixedStorage::multilevel(&[ENTRY_TREE_KEY_PREFIX], &deps.storage);
+    let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &a_lottery.round_id.to_be_bytes()], &deps.storage);
+    let (first, last) = match load_user_slot(&deps.storage, address) {
+        Some(slot) => weight_range(&entry_tree, &deficit_tree, slot)?,
+        None => (1, 0),
+    };
+
+    if first > last {
+        return to_binary(&QueryAnswer::Tickets { tickets: Uint128(0), first_ticket: None, last_ticket: None });
+    }
+    to_binary(&QueryAnswer::Tickets {
+        tickets: Uint128(last + 1 - first),
+        first_ticket: Some(Uint128(first)),
+        last_ticket: Some(Uint128(last)),
+    })
+}
+
+fn query_pending_unbondings<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+) -> StdResult<Binary> {
+    let user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, address.0.as_bytes()], &deps.storage);
+    let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, S>>::attach(&user_prefixed);
+    let user = user_store
+        .load(address.0.as_bytes())
+        .unwrap_or(UserInfo::default());
 
-    to_binary(&QueryAnswer::TotalRewards {
-        rewards: total_rewards,
+    to_binary(&QueryAnswer::PendingUnbondings {
+        unbondings: user.unbondings,
     })
 }
 
-fn query_total_deposit<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Binary> {
-    //Getting the pending_rewards
+fn query_early_exit_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+    amount: Option<Uint128>,
+    time: u64,
+) -> StdResult<Binary> {
+    let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
+    let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
+    let config: Config = configstore.load(CONFIG_KEY)?;
 
-    let supply_pool_prefixed = ReadonlyPrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &deps.storage);
-    let supply_store = TypedStore::<SupplyPool, ReadonlyPrefixedStorage<'_, S>>::attach(&supply_pool_prefixed);
-    let supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY)?;
+    let user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, address.0.as_bytes()], &deps.storage);
+    let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, S>>::attach(&user_prefixed);
+    let user = user_store
+        .load(address.0.as_bytes())
+        .unwrap_or(UserInfo::default());
 
-    to_binary(&QueryAnswer::TotalDeposits {
-        deposits: supply_pool.total_tokens_staked,
+    let withdraw_amount = amount.unwrap_or(user.amount_delegated);
+    if user.amount_delegated < withdraw_amount {
+        return Err(StdError::generic_err("Trying to withdrawing more amount than staked"));
+    }
+
+    to_binary(&QueryAnswer::EarlyExitFee {
+        fee: early_exit_fee(&config, &user, withdraw_amount, time),
+        fee_free_from: user.recent_deposit.entry_time + config.early_exit_window,
     })
 }
 
@@ -1192,7 +3175,7 @@ fn query_deposit<S: Storage, A: Api, Q: Querier>(
     let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, S>>::attach(&mut user_prefixed);
     let user = user_store
         .load(address.0.as_bytes())
-        .unwrap_or(UserInfo { amount_delegated: Uint128(0), available_tokens_for_withdraw: Uint128(0), total_won: Uint128(0), entries: vec![], entry_index: vec![] });
+        .unwrap_or(UserInfo::default());
 
     to_binary(&QueryAnswer::Balance {
         amount: (user.amount_delegated),
@@ -1202,26 +3185,32 @@ fn query_deposit<S: Storage, A: Api, Q: Querier>(
 fn query_available_funds<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     address: &HumanAddr,
+    time: u64,
 ) -> StdResult<Binary> {
     let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, address.0.as_bytes()], &deps.storage);
     let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, S>>::attach(&mut user_prefixed);
-    let user = user_store
+    let mut user = user_store
         .load(address.0.as_bytes())
-        .unwrap_or(UserInfo { amount_delegated: Uint128(0), available_tokens_for_withdraw: Uint128(0), total_won: Uint128(0), entries: vec![], entry_index: vec![] });
+        .unwrap_or(UserInfo::default());
 
     //Getting the pending_rewards
     let config_prefixed = ReadonlyPrefixedStorage::multilevel(&[CONFIG_KEY_PREFIX], &deps.storage);
     let configstore = TypedStore::<Config, ReadonlyPrefixedStorage<'_, S>>::attach(&config_prefixed);
     let config: Config = configstore.load(CONFIG_KEY)?;
 
-    if config.is_stopped_can_withdraw {
+    //The same release Withdraw does, every unbonding once the contract is WithdrawOnly
+    if config.status == ContractStatus::WithdrawOnly {
+        release_unbondings(&mut user, u64::MAX);
         return to_binary(&QueryAnswer::AvailableTokensForWithdrawl {
             amount: (user.available_tokens_for_withdraw + user.amount_delegated),
+            pending_unbondings: Uint128(0),
         });
     }
 
+    release_unbondings(&mut user, time);
     to_binary(&QueryAnswer::AvailableTokensForWithdrawl {
         amount: (user.available_tokens_for_withdraw),
+        pending_unbondings: user.unbondings.iter().fold(Uint128(0), |total, record| total + record.amount),
     })
 }
 
@@ -1329,21 +3318,22 @@ fn query_past_results<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) ->
 
 #[cfg(test)]
 mod tests {
-    use cosmwasm_std::{StdResult, InitResponse, Extern, to_binary, Uint128, HumanAddr, Coin, Env, BlockInfo, MessageInfo, ContractInfo, Querier, Binary, from_binary, ReadonlyStorage, QuerierResult, StdError};
+    use cosmwasm_std::{StdResult, InitResponse, Extern, to_binary, Uint128, HumanAddr, Coin, Env, BlockInfo, MessageInfo, ContractInfo, Querier, Binary, from_binary, ReadonlyStorage, QuerierResult, StdError, QueryRequest, WasmQuery, Empty, from_slice, log, CosmosMsg, WasmMsg};
     use cosmwasm_std::testing::{MockStorage, MockApi, MockQuerier, mock_dependencies, MOCK_CONTRACT_ADDR};
     use secret_toolkit::storage::{TypedStoreMut, TypedStore};
-    use crate::state::{Config, UserInfo, SupplyPool, Lottery, SecretContract, LotteryEntries};
-    use crate::constants::{CONFIG_KEY, VIEWING_KEY_KEY, SUPPLY_POOL_KEY, STAKING_VK, LOTTERY_KEY, USER_INFO_KEY, CONFIG_KEY_PREFIX, SUPPLY_POOL_KEY_PREFIX, LOTTERY_KEY_PREFIX, LOTTERY_ENTRY_KEY};
-    use crate::contract::{init, handle, deposit, claim_rewards, query, trigger_withdraw, withdraw, check_if_admin, check_if_triggerer, change_admin, change_triggerer, query_past_results, query_all_past_results, withdraw_excess, change_staking_contract, redelegate_to_contract, resume_contract};
-    use crate::msg::{HandleMsg, HandleAnswer, ResponseStatus, InitMsg, LPStakingRewardsResponse, RewardsInfo, QueryMsg, QueryAnswer, LPStakingQueryMsg};
+    use crate::state::{Config, UserInfo, SupplyPool, Lottery, SecretContract, RoundStatus, RoundEntry, WeightCurve, UnbondingRecord, PendingConfigChanges, FeeSchedule, Role, TimelockedAction, Multisig, ContractStatus};
+    use crate::constants::{RESPONSE_BLOCK_SIZE, CONFIG_KEY, VIEWING_KEY_KEY, SUPPLY_POOL_KEY, STAKING_VK, LOTTERY_KEY, USER_INFO_KEY, CONFIG_KEY_PREFIX, SUPPLY_POOL_KEY_PREFIX, LOTTERY_KEY_PREFIX, DEFICIT_TREE_KEY_PREFIX, DEFICIT_SLOTS_KEY_PREFIX, DEFAULT_TIMELOCK_DELAY};
+    use crate::fenwick;
+    use crate::contract::{init, handle, deposit, claim_rewards, query, trigger_withdraw, withdraw, check_role, load_role_members, authenticated_queries, query_past_results, query_all_past_results, withdraw_excess, change_staking_contract, redelegate_to_contract, set_contract_status, load_deficit_slots};
+    use crate::msg::{HandleMsg, HandleAnswer, ResponseStatus, InitMsg, LPStakingRewardsResponse, RewardsInfo, QueryMsg, QueryAnswer, LPStakingQueryMsg, LPStakingBalanceResponse, BalanceInfo, LPStakingHandleMsg};
     use crate::viewing_keys::{ViewingKey};
     use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
     use secret_toolkit::utils::Query;
     use std::any::Any;
     use cosmwasm_std::QueryResponse;
-    use secret_toolkit::incubator::{GenerationalStoreMut};
-    use secret_toolkit::incubator::generational_store::Entry;
     use crate::msg::ResponseStatus::Success;
+    use secret_toolkit::crypto::sha_256;
+    use secret_toolkit::snip20::{transfer_msg, set_viewing_key_msg};
 
     fn extract_error_msg<T: Any>(error: StdResult<T>) -> String {
         match error {
@@ -1366,6 +3356,13 @@ mod tests {
     fn init_helper(amount: Option<u128>) -> (
         StdResult<InitResponse>,
         Extern<MockStorage, MockApi, MockQuerier>,
+    ) {
+        init_with_msg_helper(amount, init_msg_helper())
+    }
+
+    fn init_with_msg_helper(amount: Option<u128>, init_msg: InitMsg) -> (
+        StdResult<InitResponse>,
+        Extern<MockStorage, MockApi, MockQuerier>,
     ) {
         let mut deps = mock_dependencies(20, &[Coin {
             amount: Uint128(amount.unwrap_or(0)),
@@ -1373,7 +3370,11 @@ mod tests {
         }]);
         let env = mock_env("admin", &[], 0);
 
-        let init_msg = InitMsg {
+        (init(&mut deps, env, init_msg), deps)
+    }
+
+    fn init_msg_helper() -> InitMsg {
+        InitMsg {
             admin: Option::from(HumanAddr("admin".to_string())),
             triggerer: Option::from(HumanAddr("triggerer".to_string())),
             token: SecretContract {
@@ -1388,9 +3389,26 @@ mod tests {
 
             prng_seed: Binary::from("I'm Batman".as_bytes()),
             triggerer_share_percentage: 100,
-        };
-
-        (init(&mut deps, env, init_msg), deps)
+            triggerer_fee_recipient: None,
+            protocol_fee_bps: None,
+            treasury: None,
+            charity_fee_bps: None,
+            charity: None,
+            prize_tiers: None,
+            draw_reveal_delay: None,
+            ticket_size: None,
+            weight_curve: None,
+            unbonding_period: None,
+            early_exit_fee_percentage: None,
+            early_exit_window: None,
+            min_deposit: None,
+            max_deposit_per_user: None,
+            pool_cap: None,
+            duration: None,
+            schedule_anchor: None,
+            timelock_delay: None,
+            multisig: None,
+        }
     }
 
     fn deposit_helper(mut mocked_deps: Extern<MockStorage, MockApi, MyMockQuerier>, env: Env) -> Extern<MockStorage, MockApi, MyMockQuerier>
@@ -1423,6 +3441,29 @@ mod tests {
         (mocked_deps, supply_pool)
     }
 
+    fn user_info_helper(mocked_deps: Extern<MockStorage, MockApi, MyMockQuerier>, user: &str) -> (Extern<MockStorage, MockApi, MyMockQuerier>, UserInfo) {
+        let user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, user.as_bytes()], &mocked_deps.storage);
+        let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&user_prefixed);
+        let user_info: UserInfo = user_store.load(user.as_bytes()).unwrap();
+        (mocked_deps, user_info)
+    }
+
+    fn mocked_init_helper(init_msg: InitMsg) -> Extern<MockStorage, MockApi, MyMockQuerier> {
+        let (init_result, deps) = init_with_msg_helper(None, init_msg);
+        init_result.unwrap();
+        deps.change_querier(|_| MyMockQuerier {})
+    }
+
+    //A deposit as the token contract forwards it, to be sent from "sefi"
+    fn deposit_msg_helper(user: &str, amount: u128) -> HandleMsg {
+        HandleMsg::Receive {
+            sender: HumanAddr(user.to_string()),
+            from: HumanAddr(user.to_string()),
+            amount: Uint128(amount),
+            msg: to_binary(&HandleMsg::Deposit {}).unwrap(),
+        }
+    }
+
     /// Just set sender and sent funds for the message. The rest uses defaults.
     /// The sender will be canonicalized internally to allow developers passing in human readable senders.
     /// This is intended for use in test code only.
@@ -1448,7 +3489,38 @@ mod tests {
     pub struct MyMockQuerier {}
 
     impl Querier for MyMockQuerier {
-        fn raw_query(&self, _request: &[u8]) -> QuerierResult {
+        fn raw_query(&self, request: &[u8]) -> QuerierResult {
+            BalanceMockQuerier { token_balance: Uint128(2000000000), staked_balance: Uint128(1500000000) }.raw_query(request)
+        }
+    }
+
+    //MyMockQuerier with the token and staked balances of the contract set apart
+    pub struct BalanceMockQuerier {
+        token_balance: Uint128,
+        staked_balance: Uint128,
+    }
+
+    impl Querier for BalanceMockQuerier {
+        fn raw_query(&self, request: &[u8]) -> QuerierResult {
+            if let Ok(QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg, .. })) = from_slice::<QueryRequest<Empty>>(request) {
+                if String::from_utf8_lossy(msg.as_slice()).contains("\"token_info\"") {
+                    let response = r#"{"token_info":{"name":"Secret Finance","symbol":"SEFI","decimals":6,"total_supply":"1000000000"}}"#;
+                    return Ok(Ok(Binary::from(response.as_bytes())));
+                }
+                if String::from_utf8_lossy(msg.as_slice()).contains("\"balance\"") {
+                    let amount = if contract_addr == HumanAddr("staking_contract".to_string()) {
+                        self.staked_balance
+                    } else {
+                        self.token_balance
+                    };
+                    let response = LPStakingBalanceResponse {
+                        balance: BalanceInfo {
+                            amount
+                        }
+                    };
+                    return Ok(to_binary(&response));
+                }
+            }
             let response = LPStakingRewardsResponse {
                 rewards: RewardsInfo {
                     rewards: Uint128(1000)
@@ -1458,6 +3530,15 @@ mod tests {
         }
     }
 
+    //A staking contract that fails every query
+    pub struct FailingMockQuerier {}
+
+    impl Querier for FailingMockQuerier {
+        fn raw_query(&self, _request: &[u8]) -> QuerierResult {
+            Ok(Err(StdError::generic_err("staking contract unavailable")))
+        }
+    }
+
     #[test]
     fn test_handle_create_viewing_key() {
         let (_init_result, mut deps) = init_helper(None);
@@ -1524,7 +3605,7 @@ mod tests {
         //2 If amount less than 1 scrt or 1000000 uscrt
         let response = deposit(&mut mocked_deps, mock_env("sefi", &[], 0), HumanAddr("Batman".to_string()), Uint128(1)).unwrap_err();
         assert_eq!(response, StdError::generic_err(
-            "Must deposit a minimum of 1000000 usefi, or 1 sefi",
+            "Must deposit a minimum of 1000000 usefi",
         ));
 
         //3)Final checking
@@ -1543,12 +3624,12 @@ mod tests {
         assert_eq!(supply_pool.pending_staking_rewards, Uint128(1000));
         assert_eq!(supply_pool.total_rewards_restaked, Uint128(0));
 
-        ////Checking lottery entries
+        ////Checking lottery entries, merged into one entry for the round
         mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
-        let mut lottery_entries = PrefixedStorage::multilevel(&[LOTTERY_ENTRY_KEY], &mut mocked_deps.storage);
-        let lottery_entries_append = GenerationalStoreMut::<LotteryEntries, PrefixedStorage<'_, MockStorage>>::attach_or_create(&mut lottery_entries).unwrap();
-        let iterator = lottery_entries_append.iter().filter(|item| matches!(item, (_, Entry::Occupied { .. })));
-        assert_eq!(iterator.count(), 11);
+        let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, "Batman".as_bytes()], &mocked_deps.storage);
+        let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
+        let user: UserInfo = user_store.load("Batman".as_bytes()).unwrap();
+        assert_eq!(user.round_entry, RoundEntry { round_id: 1, amount: Uint128(1100000000), deficit: Uint128(0) });
     }
 
     #[test]
@@ -1559,14 +3640,11 @@ mod tests {
         { mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 601)); }
 
         //LOTTERY ENTRIES
-        let mut lottery_entries = PrefixedStorage::multilevel(&[LOTTERY_ENTRY_KEY], &mut mocked_deps.storage);
-        let lottery_entries_append = GenerationalStoreMut::<LotteryEntries, PrefixedStorage<MockStorage>>::attach_or_create(&mut lottery_entries).unwrap();
-        for i in lottery_entries_append.iter() {
-            let _user_address = match i.1 {
-                Entry::Occupied { generation: _, value } => value,
-                _ => panic!("Unexpected result "),
-            };
-        }
+        //Both of Batman's deposits miss the first 601 seconds of the round
+        let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, "Batman".as_bytes()], &mocked_deps.storage);
+        let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
+        let user: UserInfo = user_store.load("Batman".as_bytes()).unwrap();
+        assert_eq!(user.round_entry, RoundEntry { round_id: 1, amount: Uint128(1000000000), deficit: Uint128(6956020) });
 
         //TRIGGERING WITHDRAW
         let _res = trigger_withdraw(&mut mocked_deps, mock_env("Batman", &[], 0), Option::from(Uint128(400000000))).unwrap();
@@ -1574,7 +3652,7 @@ mod tests {
         let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
         let user: UserInfo = user_store.load("Batman".as_bytes()).unwrap();
         assert_eq!(user.available_tokens_for_withdraw.0, 400000000);
-        assert_eq!(user.entry_index.len(), 2);
+        assert_eq!(user.round_entry, RoundEntry { round_id: 1, amount: Uint128(600000000), deficit: Uint128(4173612) });
 
         //Checking Lottery Entries
         let _res = trigger_withdraw(&mut mocked_deps, mock_env("Batman", &[], 0), Option::from(Uint128(400000000))).unwrap();
@@ -1582,7 +3660,7 @@ mod tests {
         let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
         let user: UserInfo = user_store.load("Batman".as_bytes()).unwrap();
         assert_eq!(user.available_tokens_for_withdraw.0, 800000000);
-        assert_eq!(user.entry_index.len(), 1);
+        assert_eq!(user.round_entry, RoundEntry { round_id: 1, amount: Uint128(200000000), deficit: Uint128(1391204) });
     }
 
     #[test]
@@ -1594,34 +3672,640 @@ mod tests {
         // DEPOSIT HELPER
         let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
         {
-            let _ = claim_rewards(&mut mocked_deps, _env);
+            let _ = claim_rewards(&mut mocked_deps, _env, &[]);
             let env = mock_env("sefi", &[], 0);
             mocked_deps = deposit_helper(mocked_deps, env);
         }
-        //LOADING LOTTERY
+        //LOADING LOTTERY
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+
+        let env = mock_env("triggerer", &[], a_lottery.end_time);
+        let response = claim_rewards(&mut mocked_deps, env, &[]);
+
+        let winner = match from_binary(&response.unwrap().data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: ResponseStatus::Success, winners, .. } => winners[0].0.clone(),
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, winner.0.as_bytes()], &mocked_deps.storage);
+        let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
+        let user: UserInfo = user_store.load(winner.0.as_bytes()).unwrap();
+        assert_eq!(user.available_tokens_for_withdraw.0, 10890);
+        assert_eq!(user.total_won.0, 10890);
+
+        let (_, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.total_rewards_restaked.0, 0);
+        assert_eq!(supply_pool.pending_staking_rewards.0, 0);
+        assert_eq!(supply_pool.total_tokens_staked.0, 1008000000);
+    }
+
+    #[test]
+    fn test_claim_rewards_prize_tiers() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        //Invalid tiers are rejected
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 0), HandleMsg::ChangePrizeTiers { tiers: vec![50, 30] });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Prize tiers must be non-zero percentages adding up to 100"));
+        let res = handle(&mut mocked_deps, mock_env("not-admin", &[], 0), HandleMsg::ChangePrizeTiers { tiers: vec![50, 30, 20] });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address"));
+
+        let _res = handle(&mut mocked_deps, mock_env("admin", &[], 0), HandleMsg::ChangePrizeTiers { tiers: vec![50, 30, 20] }).unwrap();
+
+        //The tiers apply once the current round is drawn
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, round_id, draw_time } => {
+                assert_eq!(changes.prize_tiers, Some(vec![50, 30, 20]));
+                assert_eq!((round_id, draw_time), (1, 86400));
+            }
+            _ => panic!("Unexpected result from query"),
+        }
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 10));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.prize_tiers, vec![50, 30, 20]);
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 86400));
+
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]);
+        let winners = match from_binary(&response.unwrap().data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: ResponseStatus::Success, winners, .. } => winners,
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        //Three distinct winners sharing the whole prize 50/30/20
+        assert_eq!(winners.len(), 3);
+        assert_ne!(winners[0].0, winners[1].0);
+        assert_ne!(winners[0].0, winners[2].0);
+        assert_ne!(winners[1].0, winners[2].0);
+        assert_eq!(winners[0].1, Uint128(5445));
+        assert_eq!(winners[1].1, Uint128(3267));
+        assert_eq!(winners[2].1, Uint128(2178));
+
+        for (winner, prize) in winners {
+            let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, winner.0.as_bytes()], &mocked_deps.storage);
+            let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
+            let user: UserInfo = user_store.load(winner.0.as_bytes()).unwrap();
+            assert_eq!(user.total_won, prize);
+        }
+    }
+
+    #[test]
+    fn test_commit_reveal_draw() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+
+        let secret = Binary::from("Why so serious?".as_bytes());
+        let entropy_hash = Binary(sha_256(&secret.0).to_vec());
+
+        //Only the triggerer can commit, and only after the lottery ended
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], a_lottery.end_time), HandleMsg::CommitDraw { entropy_hash: entropy_hash.clone() });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address and triggerer address"));
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time - 1), HandleMsg::CommitDraw { entropy_hash: entropy_hash.clone() });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Lottery end height is in the future"));
+
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), HandleMsg::CommitDraw { entropy_hash: entropy_hash.clone() }).unwrap();
+        let reveal_height = match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::CommitDraw { status: Success, reveal_height } => reveal_height,
+            _ => panic!("Unexpected result from handle"),
+        };
+        assert_eq!(reveal_height, a_lottery.end_time + 2);
+
+        //Entry set is frozen until the reveal
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], a_lottery.end_time + 1), HandleMsg::TriggerWithdraw { amount: None });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Withdrawals are available again once it is revealed"));
+        let res = deposit(&mut mocked_deps, mock_env("sefi", &[], a_lottery.end_time + 1), HumanAddr("Batman".to_string()), Uint128(1000000));
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Deposits are available again once it is revealed"));
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time + 1), HandleMsg::CommitDraw { entropy_hash });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw has already been committed and is waiting to be revealed"));
+
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time + 1), HandleMsg::RevealDraw { entropy: secret.clone() });
+        assert_eq!(res.unwrap_err(), StdError::generic_err(format!("Draw can be revealed from block {}", reveal_height)));
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], reveal_height), HandleMsg::RevealDraw { entropy: secret.clone() });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Only the address that committed the draw can reveal it"));
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], reveal_height), HandleMsg::RevealDraw { entropy: Binary::from("I'm Batman".as_bytes()) });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Revealed entropy does not match the committed hash"));
+
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], reveal_height), HandleMsg::RevealDraw { entropy: secret.clone() }).unwrap();
+        let winners = match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, .. } => winners,
+            _ => panic!("Unexpected result from handle"),
+        };
+        assert_eq!(winners.len(), 1);
+
+        //The commitment is consumed
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], reveal_height), HandleMsg::RevealDraw { entropy: secret });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No draw has been committed or the commitment has expired"));
+        let _res = handle(&mut mocked_deps, mock_env("Batman", &[], reveal_height), HandleMsg::TriggerWithdraw { amount: None }).unwrap();
+        deposit(&mut mocked_deps, mock_env("sefi", &[], reveal_height), HumanAddr("Batman".to_string()), Uint128(1000000)).unwrap();
+    }
+
+    #[test]
+    fn test_draw_entropy_fixed_at_commit() {
+        let secret = Binary::from("Why so serious?".as_bytes());
+        let commit_msg = HandleMsg::CommitDraw { entropy_hash: Binary(sha_256(&secret.0).to_vec()) };
+
+        //Revealing in a later block draws from the same entropy
+        let mut entropy_hashes = vec![];
+        for reveal_time in &[86402, 86450] {
+            let (_init_result, deps) = init_helper(None);
+            let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+            mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+            handle(&mut mocked_deps, mock_env("triggerer", &[], 86400), commit_msg.clone()).unwrap();
+            handle(&mut mocked_deps, mock_env("triggerer", &[], *reveal_time), HandleMsg::RevealDraw { entropy: secret.clone() }).unwrap();
+            match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 }).unwrap()).unwrap() {
+                QueryAnswer::RoundInfo { round } => entropy_hashes.push(round.entropy_hash),
+                _ => panic!("Unexpected result from query"),
+            }
+        }
+        assert_eq!(entropy_hashes[0], entropy_hashes[1]);
+
+        //A Triggerer that misses its reveal window commits again, the commitment stays pending until it expires
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 86400), commit_msg.clone()).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 86502), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw has already been committed and is waiting to be revealed"));
+        let fresh_secret = Binary::from("Let's put a smile on that face".as_bytes());
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 86503), HandleMsg::CommitDraw { entropy_hash: Binary(sha_256(&fresh_secret.0).to_vec()) }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 86505), HandleMsg::RevealDraw { entropy: secret });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Revealed entropy does not match the committed hash"));
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 86505), HandleMsg::RevealDraw { entropy: fresh_secret }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { round_id, .. } => assert_eq!(round_id, 2),
+            _ => panic!("Unexpected result from query"),
+        }
+    }
+
+    #[test]
+    fn test_claim_rewards_rollover() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        //Rewards accrue but nobody is left in the pool at the end of the round
+        deposit(&mut mocked_deps, mock_env("sefi", &[], 0), HumanAddr("Batman".to_string()), Uint128(500000000)).unwrap();
+        trigger_withdraw(&mut mocked_deps, mock_env("Batman", &[], 10), None).unwrap();
+
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]).unwrap();
+        match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, rolled_over: true } => assert!(winners.is_empty()),
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        let (rolled_over_prize, rolled_over_rounds) = match from_binary(&query(&mocked_deps, QueryMsg::Jackpot {}).unwrap()).unwrap() {
+            QueryAnswer::Jackpot { rolled_over_prize, rolled_over_rounds } => (rolled_over_prize, rolled_over_rounds),
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(rolled_over_prize, Uint128(2000));
+        assert_eq!(rolled_over_rounds, 1);
+
+        //Recorded in history, and the jackpot is not excess for the admin
+        let past_rewards = match from_binary(&query_all_past_results(&mocked_deps).unwrap()).unwrap() {
+            QueryAnswer::PastAllRecords { past_rewards } => past_rewards,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(past_rewards, vec![(0, a_lottery.end_time)]);
+        let res = withdraw_excess(&mut mocked_deps, mock_env("admin", &[], a_lottery.end_time));
+        assert_eq!(res.unwrap_err(), StdError::generic_err("There are no excess rewards to withdraw"));
+
+        //Next round's winner takes the rolled over prize as well
+        deposit(&mut mocked_deps, mock_env("sefi", &[], a_lottery.end_time), HumanAddr("Batman".to_string()), Uint128(500000000)).unwrap();
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time + a_lottery.duration), &[]).unwrap();
+        let winners = match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, rolled_over: false } => winners,
+            _ => panic!("Unexpected result from handle"),
+        };
+        assert_eq!(winners, vec![(HumanAddr("Batman".to_string()), Uint128(3960))]);
+
+        let (_, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.rolled_over_prize, Uint128(0));
+        assert_eq!(supply_pool.rolled_over_rounds, 0);
+    }
+
+    #[test]
+    fn test_claim_rewards_weight_index() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        //Superman joins half way through the round and then takes half of that deposit out again
+        deposit(&mut mocked_deps, mock_env("sefi", &[], 0), HumanAddr("Batman".to_string()), Uint128(100000000)).unwrap();
+        deposit(&mut mocked_deps, mock_env("sefi", &[], 43200), HumanAddr("Superman".to_string()), Uint128(100000000)).unwrap();
+        trigger_withdraw(&mut mocked_deps, mock_env("Superman", &[], 43200), Some(Uint128(50000000))).unwrap();
+
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+        let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &1u64.to_be_bytes()], &mocked_deps.storage);
+        assert_eq!(fenwick::total(&deficit_tree), 25000000);
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]).unwrap();
+
+        //The drawn round's deficits are removed
+        let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &1u64.to_be_bytes()], &mocked_deps.storage);
+        assert_eq!(fenwick::total(&deficit_tree), 0);
+
+        //Batman counts in full and Superman's remaining 50 sefi for half the round
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(125000000));
+        assert_eq!(round.participants, 2);
+
+        //Both count in full in the next round
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time + a_lottery.duration), &[]).unwrap();
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 2 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(150000000));
+    }
+
+    #[test]
+    fn test_round_entry_compaction() {
+        let mut mocked_deps = mocked_init_helper(init_msg_helper());
+
+        //Deposits in the same round are merged into one round entry
+        for time in &[0, 21600, 43200] {
+            handle(&mut mocked_deps, mock_env("sefi", &[], *time), deposit_msg_helper("Batman", 100000000)).unwrap();
+        }
+        let (mut mocked_deps, user) = user_info_helper(mocked_deps, "Batman");
+        assert_eq!(user.round_entry, RoundEntry { round_id: 1, amount: Uint128(300000000), deficit: Uint128(75000000) });
+        assert_eq!(load_deficit_slots(&mocked_deps.storage, 1).unwrap(), vec![1]);
+
+        //The drawn round's deficits and their slot list are removed
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &1u64.to_be_bytes()], &mocked_deps.storage);
+        assert_eq!(fenwick::total(&deficit_tree), 0);
+        let deficit_slots_prefixed = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_SLOTS_KEY_PREFIX], &mocked_deps.storage);
+        assert_eq!(deficit_slots_prefixed.get(&1u64.to_be_bytes()), None);
+
+        //The first deposit of the next round folds the old entry into the carried over amount
+        handle(&mut mocked_deps, mock_env("sefi", &[], 129600), deposit_msg_helper("Batman", 100000000)).unwrap();
+        let (mut mocked_deps, user) = user_info_helper(mocked_deps, "Batman");
+        assert_eq!(user.amount_delegated, Uint128(400000000));
+        assert_eq!(user.round_entry, RoundEntry { round_id: 2, amount: Uint128(100000000), deficit: Uint128(50000000) });
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 172800), &[]).unwrap();
+        let deficit_tree = ReadonlyPrefixedStorage::multilevel(&[DEFICIT_TREE_KEY_PREFIX, &2u64.to_be_bytes()], &mocked_deps.storage);
+        assert_eq!(fenwick::total(&deficit_tree), 0);
+
+        //A stale entry counts in full, and the compaction leaves the recorded rounds as they were drawn
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 259200), &[]).unwrap();
+        for (round_id, total_weight) in &[(1, 225000000), (2, 350000000), (3, 400000000)] {
+            match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: *round_id }).unwrap()).unwrap() {
+                QueryAnswer::RoundInfo { round } => assert_eq!(round.total_weight, Uint128(*total_weight)),
+                _ => panic!("Unexpected result from query"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_weight_curves() {
+        let mut init_msg = init_msg_helper();
+        init_msg.weight_curve = Some(WeightCurve::Step { cutoff_percentage: 50 });
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        //Only deposits made in the first half of the round count, and those count in full
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 100000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 43199), deposit_msg_helper("Flash", 100000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 43200), deposit_msg_helper("Superman", 100000000)).unwrap();
+
+        let handle_result = handle(&mut mocked_deps, mock_env("admin", &[], 50000), HandleMsg::ChangeWeightCurve { curve: WeightCurve::Step { cutoff_percentage: 0 } });
+        assert_eq!(handle_result.unwrap_err(), StdError::generic_err("Step cut-off must be between 1 and 100 percent"));
+        let handle_result = handle(&mut mocked_deps, mock_env("Batman", &[], 50000), HandleMsg::ChangeWeightCurve { curve: WeightCurve::SquareRoot });
+        assert!(handle_result.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 50000), HandleMsg::ChangeWeightCurve { curve: WeightCurve::SquareRoot }).unwrap();
+
+        //The current round keeps its curve, the new one waits for the draw
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, .. } => assert_eq!(changes.weight_curve, Some(WeightCurve::SquareRoot)),
+            _ => panic!("Unexpected result from query"),
+        }
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.weight_curve, WeightCurve::Step { cutoff_percentage: 50 });
+        match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { weight_curve, .. } => assert_eq!(weight_curve, WeightCurve::Step { cutoff_percentage: 50 }),
+            _ => panic!("Unexpected result from query"),
+        }
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(200000000));
+        assert_eq!(round.weight_curve, WeightCurve::Step { cutoff_percentage: 50 });
+
+        //Square roots of 400, 100 and 100 sefi, scaled by SQRT_WEIGHT_SCALE
+        match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { weight_curve, .. } => assert_eq!(weight_curve, WeightCurve::SquareRoot),
+            _ => panic!("Unexpected result from query"),
+        }
+        handle(&mut mocked_deps, mock_env("sefi", &[], 86400), deposit_msg_helper("Batman", 300000000)).unwrap();
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 172800), &[]).unwrap();
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 2 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(40000000));
+        assert_eq!(round.weight_curve, WeightCurve::SquareRoot);
+    }
+
+    #[test]
+    fn test_fee_schedule() {
+        let mut init_msg = init_msg_helper();
+        init_msg.protocol_fee_bps = Some(500);
+        init_msg.treasury = Some(HumanAddr("treasury".to_string()));
+        init_msg.charity_fee_bps = Some(300);
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("A charity fee needs a charity address"));
+
+        init_msg.charity = Some(HumanAddr("charity".to_string()));
+        init_msg.triggerer_fee_recipient = Some(HumanAddr("keeper".to_string()));
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        let fees = FeeSchedule {
+            triggerer_fee_bps: 1000,
+            triggerer_fee_recipient: HumanAddr("keeper".to_string()),
+            protocol_fee_bps: 2000,
+            treasury: HumanAddr("treasury".to_string()),
+            charity_fee_bps: 1,
+            charity: Some(HumanAddr("charity".to_string())),
+        };
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 0), HandleMsg::ChangeFeeSchedule { fees });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Fees can be at most 3000 basis points in total"));
+
+        //1% to the triggerer, 5% to the treasury and 3% to the charity out of 11000 of rewards
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { winners, .. } => assert_eq!(winners[0].1, Uint128(10010)),
+            _ => panic!("Unexpected result from handle"),
+        }
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!((supply_pool.triggering_cost, supply_pool.protocol_fees, supply_pool.charity_fees), (Uint128(110), Uint128(550), Uint128(330)));
+
+        //Each recipient withdraws its own fees, the triggerer fee goes to its recipient rather than the Triggerer
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 86400), HandleMsg::WithdrawFees {});
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No fees to withdraw for this address"));
+        for (recipient, expected) in &[("treasury", 550), ("charity", 330), ("keeper", 110)] {
+            let response = handle(&mut mocked_deps, mock_env(*recipient, &[], 86400), HandleMsg::WithdrawFees {}).unwrap();
+            match from_binary(&response.data.unwrap()).unwrap() {
+                HandleAnswer::WithdrawFees { status: Success, amount } => assert_eq!(amount, Uint128(*expected)),
+                _ => panic!("Unexpected result from handle"),
+            }
+        }
+        let res = handle(&mut mocked_deps, mock_env("treasury", &[], 86400), HandleMsg::WithdrawFees {});
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No fees to withdraw for this address"));
+    }
+
+    #[test]
+    fn test_unbonding() {
+        let mut init_msg = init_msg_helper();
+        init_msg.unbonding_period = Some(100);
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 100000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("Batman", &[], 0), HandleMsg::SetViewingKey { key: "key".to_string(), padding: None }).unwrap();
+        handle(&mut mocked_deps, mock_env("Batman", &[], 10), HandleMsg::TriggerWithdraw { amount: Some(Uint128(40000000)) }).unwrap();
+        handle(&mut mocked_deps, mock_env("Batman", &[], 60), HandleMsg::TriggerWithdraw { amount: Some(Uint128(10000000)) }).unwrap();
+
+        let query_msg = QueryMsg::PendingUnbondings { address: HumanAddr("Batman".to_string()), key: "key".to_string() };
+        let unbondings = match from_binary(&query(&mocked_deps, query_msg.clone()).unwrap()).unwrap() {
+            QueryAnswer::PendingUnbondings { unbondings } => unbondings,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(unbondings, vec![
+            UnbondingRecord { amount: Uint128(40000000), start_time: 10, release_time: 110 },
+            UnbondingRecord { amount: Uint128(10000000), start_time: 60, release_time: 160 },
+        ]);
+
+        //A matured unbonding is reported as available before any Withdraw moves it
+        let query_available = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>, time: u64| {
+            let query_msg = QueryMsg::AvailableTokensForWithdrawl { address: HumanAddr("Batman".to_string()), key: "key".to_string(), time };
+            match from_binary(&query(deps, query_msg).unwrap()).unwrap() {
+                QueryAnswer::AvailableTokensForWithdrawl { amount, pending_unbondings } => (amount, pending_unbondings),
+                _ => panic!("Unexpected result from query"),
+            }
+        };
+        assert_eq!(query_available(&mocked_deps, 109), (Uint128(0), Uint128(50000000)));
+        assert_eq!(query_available(&mocked_deps, 110), (Uint128(40000000), Uint128(10000000)));
+        assert_eq!(query_available(&mocked_deps, 160), (Uint128(50000000), Uint128(0)));
+
+        //Nothing has matured yet
+        let handle_result = handle(&mut mocked_deps, mock_env("Batman", &[], 109), HandleMsg::Withdraw { amount: None });
+        assert_eq!(handle_result.unwrap_err(), StdError::generic_err("No tokens available for withdraw"));
+
+        //Only the first record has matured
+        let handle_result = handle(&mut mocked_deps, mock_env("Batman", &[], 110), HandleMsg::Withdraw { amount: Some(Uint128(50000000)) });
+        assert_eq!(handle_result.unwrap_err(), StdError::generic_err("Withdrawing more amount than Available tokens for withdraw"));
+        handle(&mut mocked_deps, mock_env("Batman", &[], 110), HandleMsg::Withdraw { amount: None }).unwrap();
+
+        let (mut mocked_deps, user) = user_info_helper(mocked_deps, "Batman");
+        assert_eq!(user.amount_delegated, Uint128(50000000));
+        assert_eq!(user.available_tokens_for_withdraw, Uint128(0));
+        assert_eq!(user.unbondings, vec![UnbondingRecord { amount: Uint128(10000000), start_time: 60, release_time: 160 }]);
+
+        handle(&mut mocked_deps, mock_env("Batman", &[], 160), HandleMsg::Withdraw { amount: Some(Uint128(10000000)) }).unwrap();
+        match from_binary(&query(&mocked_deps, query_msg).unwrap()).unwrap() {
+            QueryAnswer::PendingUnbondings { unbondings } => assert!(unbondings.is_empty()),
+            _ => panic!("Unexpected result from query"),
+        }
+    }
+
+    #[test]
+    fn test_early_exit_fee() {
+        let mut init_msg = init_msg_helper();
+        init_msg.early_exit_fee_percentage = Some(10001);
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("Early exit fee can not be more than 100 percent"));
+
+        //5% within an hour of the last deposit
+        init_msg.early_exit_fee_percentage = Some(500);
+        init_msg.early_exit_window = Some(3600);
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 100000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 10000), deposit_msg_helper("Batman", 20000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("Batman", &[], 10000), HandleMsg::SetViewingKey { key: "key".to_string(), padding: None }).unwrap();
+
+        //Only the part taken from the recent deposit is charged
+        let query_fee = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>, amount: Option<Uint128>, time: u64| {
+            let query_msg = QueryMsg::EarlyExitFee { address: HumanAddr("Batman".to_string()), key: "key".to_string(), amount, time };
+            match from_binary(&query(deps, query_msg).unwrap()).unwrap() {
+                QueryAnswer::EarlyExitFee { fee, fee_free_from } => (fee, fee_free_from),
+                _ => panic!("Unexpected result from query"),
+            }
+        };
+        assert_eq!(query_fee(&mocked_deps, Some(Uint128(50000000)), 10100), (Uint128(0), 13600));
+        assert_eq!(query_fee(&mocked_deps, None, 10100), (Uint128(1000000), 13600));
+        assert_eq!(query_fee(&mocked_deps, None, 13600), (Uint128(0), 13600));
+
+        let response = handle(&mut mocked_deps, mock_env("Batman", &[], 10100), HandleMsg::TriggerWithdraw { amount: Some(Uint128(110000000)) }).unwrap();
+        match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::TriggerWithdraw { status: Success, early_exit_fee } => assert_eq!(early_exit_fee, Uint128(500000)),
+            _ => panic!("Unexpected result from handle"),
+        }
+        let (mut mocked_deps, user) = user_info_helper(mocked_deps, "Batman");
+        assert_eq!(user.available_tokens_for_withdraw, Uint128(109500000));
+        assert_eq!(user.recent_deposit.amount, Uint128(10000000));
+
+        //A top-up moves the window by its share of the recent deposits only
+        handle(&mut mocked_deps, mock_env("sefi", &[], 11800), deposit_msg_helper("Batman", 10000000)).unwrap();
+        assert_eq!(query_fee(&mocked_deps, None, 11800), (Uint128(1000000), 14500));
+
+        //The fee is kept for the prize, out of reach of WithdrawExcess
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.total_tokens_staked, Uint128(20000000));
+        assert_eq!(supply_pool.early_exit_fees, Uint128(500000));
+        handle(&mut mocked_deps, mock_env("admin", &[], 11800), HandleMsg::SetContractStatus { status: ContractStatus::WithdrawOnly, reason: None }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 11800), HandleMsg::WithdrawExcess {}).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 11800 + DEFAULT_TIMELOCK_DELAY), HandleMsg::ExecuteAction { id: 0 }).unwrap();
+        let (_mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.total_rewards_restaked, Uint128(500000));
+    }
+
+    #[test]
+    fn test_deposit_limits() {
+        let mut init_msg = init_msg_helper();
+        init_msg.min_deposit = Some(Uint128(5000000));
+        init_msg.max_deposit_per_user = Some(Uint128(1000000));
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("Per-address limit can not be below the minimum deposit"));
+
+        init_msg.max_deposit_per_user = Some(Uint128(100000000));
+        init_msg.pool_cap = Some(Uint128(150000000));
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 4999999));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Must deposit a minimum of 5000000 usefi"));
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 90000000)).unwrap();
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 10000001));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Deposit would take this address over the limit of 100000000 usefi delegated per address"));
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 10000000)).unwrap();
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Superman", 60000000));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Deposit would take the pool over its cap of 150000000 usefi"));
+
+        //Only the admin can raise the caps
+        let handle_msg = HandleMsg::ChangeDepositLimits { min_deposit: Uint128(1000000), max_deposit_per_user: None, pool_cap: Some(Uint128(200000000)) };
+        assert!(handle(&mut mocked_deps, mock_env("Batman", &[], 0), handle_msg.clone()).is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 0), handle_msg).unwrap();
+
+        //The new limits apply once the current round is drawn
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Superman", 60000000));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Deposit would take the pool over its cap of 150000000 usefi"));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 86400), deposit_msg_helper("Superman", 60000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 86400), deposit_msg_helper("Batman", 40000000)).unwrap();
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 86400), deposit_msg_helper("Batman", 1000000));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Deposit would take the pool over its cap of 200000000 usefi"));
+        let response = handle(&mut mocked_deps, mock_env("sefi", &[], 86400), deposit_msg_helper("Batman", u128::MAX));
+        assert_eq!(response.unwrap_err(), StdError::generic_err("Deposit amount overflows the pool total"));
+    }
+
+    #[test]
+    fn test_ticket_mode() {
+        let mut init_msg = init_msg_helper();
+        init_msg.ticket_size = Some(Uint128(0));
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("Ticket size must be greater than zero"));
+
+        init_msg.ticket_size = Some(Uint128(1000000));
+        init_msg.prize_tiers = Some(vec![60, 40]);
+        let mut mocked_deps = mocked_init_helper(init_msg);
+
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 5000000)).unwrap();
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Superman", 3500000)).unwrap();
+        //A later deposit extends the holder's own range, shifting the tickets after it
+        handle(&mut mocked_deps, mock_env("sefi", &[], 0), deposit_msg_helper("Batman", 2000000)).unwrap();
+        for user in &["Batman", "Superman"] {
+            handle(&mut mocked_deps, mock_env(*user, &[], 0), HandleMsg::SetViewingKey { key: "key".to_string(), padding: None }).unwrap();
+        }
+
+        let query_tickets = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>, user: &str| {
+            match from_binary(&query(deps, QueryMsg::Tickets { address: HumanAddr(user.to_string()), key: "key".to_string() }).unwrap()).unwrap() {
+                QueryAnswer::Tickets { tickets, first_ticket, last_ticket } => (tickets, first_ticket, last_ticket),
+                _ => panic!("Unexpected result from query"),
+            }
+        };
+        assert_eq!(query_tickets(&mocked_deps, "Batman"), (Uint128(7), Some(Uint128(1)), Some(Uint128(7))));
+        assert_eq!(query_tickets(&mocked_deps, "Superman"), (Uint128(3), Some(Uint128(8)), Some(Uint128(10))));
+
+        //Only full tickets count
+        handle(&mut mocked_deps, mock_env("Superman", &[], 10), HandleMsg::TriggerWithdraw { amount: Some(Uint128(2000000)) }).unwrap();
+        assert_eq!(query_tickets(&mocked_deps, "Superman"), (Uint128(1), Some(Uint128(8)), Some(Uint128(8))));
+        match from_binary(&query(&mocked_deps, QueryMsg::TicketInfo {}).unwrap()).unwrap() {
+            QueryAnswer::TicketInfo { ticket_size, total_tickets, holders } => {
+                assert_eq!(ticket_size, Some(Uint128(1000000)));
+                assert_eq!(total_tickets, Uint128(8));
+                assert_eq!(holders, 2);
+            }
+            _ => panic!("Unexpected result from query"),
+        }
+
+        //Numbers are places in the draw, not fixed at purchase. A withdrawal by an earlier holder moves every later range.
+        handle(&mut mocked_deps, mock_env("Batman", &[], 10), HandleMsg::TriggerWithdraw { amount: Some(Uint128(1000000)) }).unwrap();
+        assert_eq!(query_tickets(&mocked_deps, "Batman"), (Uint128(6), Some(Uint128(1)), Some(Uint128(6))));
+        assert_eq!(query_tickets(&mocked_deps, "Superman"), (Uint128(1), Some(Uint128(7)), Some(Uint128(7))));
+
+        //Both tiers are drawn from distinct holders and the winning ticket numbers are recorded
         let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
         let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
         let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
-
-        let env = mock_env("triggerer", &[], a_lottery.end_time);
-        let response = claim_rewards(&mut mocked_deps, env);
-
-        let winner = match from_binary(&response.unwrap().data.unwrap()).unwrap() {
-            HandleAnswer::ClaimRewards { status: ResponseStatus::Success, winner: winner_addr } => winner_addr,
-            HandleAnswer::ClaimRewards { status: ResponseStatus::Failure, winner: winner_addr } => winner_addr,
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]).unwrap();
+        let winners = match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, .. } => winners,
             _ => panic!("Unexpected result from handle"),
         };
+        assert_eq!(winners.len(), 2);
+        assert_ne!(winners[0].0, winners[1].0);
 
-        let mut user_prefixed = ReadonlyPrefixedStorage::multilevel(&[USER_INFO_KEY, winner.0.as_bytes()], &mocked_deps.storage);
-        let user_store = TypedStore::<UserInfo, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&mut user_prefixed);
-        let user: UserInfo = user_store.load(winner.0.as_bytes()).unwrap();
-        assert_eq!(user.available_tokens_for_withdraw.0, 10890);
-        assert_eq!(user.total_won.0, 10890);
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(7));
+        assert_eq!(round.participants, 2);
+        for ((winner, _), ticket) in winners.iter().zip(round.winning_tickets.iter()) {
+            if winner == &HumanAddr("Superman".to_string()) {
+                assert_eq!(*ticket, Uint128(7));
+            } else {
+                assert!(Uint128(1) <= *ticket && *ticket <= Uint128(6));
+            }
+        }
 
-        let (_, supply_pool) = supply_pool_helper(mocked_deps);
-        assert_eq!(supply_pool.total_rewards_restaked.0, 0);
-        assert_eq!(supply_pool.pending_staking_rewards.0, 0);
-        assert_eq!(supply_pool.total_tokens_staked.0, 1008000000);
+        //Leaving the pool gives up every ticket
+        handle(&mut mocked_deps, mock_env("Superman", &[], a_lottery.end_time + 10), HandleMsg::TriggerWithdraw { amount: None }).unwrap();
+        assert_eq!(query_tickets(&mocked_deps, "Superman"), (Uint128(0), None, None));
+        assert_eq!(query_tickets(&mocked_deps, "Batman"), (Uint128(6), Some(Uint128(1)), Some(Uint128(6))));
+
+        //Tickets bought after the round ended only count from the next round
+        let round_end = match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { end_time, .. } => end_time,
+            _ => panic!("Unexpected result from query"),
+        };
+        handle(&mut mocked_deps, mock_env("sefi", &[], round_end), deposit_msg_helper("Batman", 3000000)).unwrap();
+        assert_eq!(query_tickets(&mocked_deps, "Batman"), (Uint128(6), Some(Uint128(1)), Some(Uint128(6))));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], round_end), &[]).unwrap();
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 2 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.total_weight, Uint128(6));
+        assert_eq!(query_tickets(&mocked_deps, "Batman"), (Uint128(9), Some(Uint128(1)), Some(Uint128(9))));
     }
 
     #[test]
@@ -1670,21 +4354,122 @@ mod tests {
         let lottery_store = TypedStoreMut::<Lottery, PrefixedStorage<'_, MockStorage>>::attach(&mut lottery_prefix);
         let lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
 
-        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], lottery.end_time)).unwrap();
+        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], lottery.end_time), &[]).unwrap();
 
         let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
         assert_eq!(supply_pool.triggering_cost, Uint128(30));
 
+        //Paid to the triggerer, nobody else can take it
         let handlemsg = HandleMsg::TriggeringCostWithdraw {};
-        let _res = handle(&mut mocked_deps, mock_env("admin", &[], 10), handlemsg);
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), handlemsg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No fees to withdraw for this address"));
+        let _res = handle(&mut mocked_deps, mock_env("triggerer", &[], 10), handlemsg).unwrap();
 
         let (_, supply_pool) = supply_pool_helper(mocked_deps);
         assert_eq!(supply_pool.triggering_cost, Uint128(0));
     }
 
+    #[test]
+    fn testing_permissionless_draw_bounty() {
+        let (_init_result, deps) = init_helper(Some(800000000));
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        let secret = Binary::from("robin".as_bytes());
+        let commit_msg = HandleMsg::CommitDraw { entropy_hash: Binary(sha_256(&secret.0).to_vec()) };
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], 86400), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address and triggerer address"));
+
+        let msg = HandleMsg::ChangePermissionlessDraws { enabled: true, max_bounty: Uint128(20) };
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], 10), msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address"));
+        let _res = handle(&mut mocked_deps, mock_env("admin", &[], 10), msg).unwrap();
+
+        //The round that was running when they were enabled is still drawn by the triggerer
+        deposit(&mut mocked_deps, mock_env("sefi", &[], 10), HumanAddr("batman".to_string()), Uint128(5000000000)).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], 86400), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address and triggerer address"));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.triggering_cost, Uint128(20));
+
+        let env = mock_env("sefi", &[], 87000);
+        deposit(&mut mocked_deps, env.clone(), HumanAddr("batman".to_string()), Uint128(5000000000)).unwrap();
+        deposit(&mut mocked_deps, env.clone(), HumanAddr("batman".to_string()), Uint128(5000000000)).unwrap();
+        let end_time = match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { end_time, .. } => end_time,
+            _ => panic!("Unexpected result from query"),
+        };
+
+        //Anyone can draw once the lottery ended, and the capped bounty is paid to them directly
+        let _res = handle(&mut mocked_deps, mock_env("Robin", &[], end_time), commit_msg).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], end_time + 2), HandleMsg::RevealDraw { entropy: secret }).unwrap();
+        assert_eq!(res.messages[1], transfer_msg(
+            HumanAddr("Robin".to_string()),
+            Uint128(20),
+            None,
+            RESPONSE_BLOCK_SIZE,
+            "".to_string(),
+            HumanAddr("sefi".to_string()),
+        ).unwrap());
+        let winners = match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, .. } => winners,
+            _ => panic!("Unexpected result from handle"),
+        };
+        assert_eq!(winners, vec![(HumanAddr("batman".to_string()), Uint128(2980))]);
+
+        let (_, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.triggering_cost, Uint128(20));
+    }
+
+    #[test]
+    fn test_permissionless_commitment() {
+        let (_init_result, deps) = init_helper(Some(800000000));
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangePermissionlessDraws { enabled: true, max_bounty: Uint128(20) }).unwrap();
+        //Permissionless draws start with the next round
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 10));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+
+        let secret = Binary::from("robin".as_bytes());
+        let commit_msg = HandleMsg::CommitDraw { entropy_hash: Binary(sha_256(&secret.0).to_vec()) };
+        handle(&mut mocked_deps, mock_env("Robin", &[], 172800), commit_msg.clone()).unwrap();
+
+        //A permissionless commitment freezes every entry as well, not only the committer's own
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 172801), HandleMsg::TriggerWithdraw { amount: Some(Uint128(1000000)) });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Withdrawals are available again once it is revealed"));
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 172801), deposit_msg_helper("Batman", 1000000));
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Deposits are available again once it is revealed"));
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 172801), deposit_msg_helper("Robin", 1000000));
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Deposits are available again once it is revealed"));
+
+        //The triggerer takes over once Robin could have revealed, and Robin is out for the round
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 172803), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw has already been committed and is waiting to be revealed"));
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 172802), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw has already been committed and is waiting to be revealed"));
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 172803), commit_msg.clone()).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], 172805), HandleMsg::RevealDraw { entropy: secret.clone() });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Only the address that committed the draw can reveal it"));
+
+        //and so does the Triggerer's
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 172804), HandleMsg::TriggerWithdraw { amount: Some(Uint128(1000000)) });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("A draw is in progress. Withdrawals are available again once it is revealed"));
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 172805), HandleMsg::RevealDraw { entropy: secret }).unwrap();
+
+        //A permissionless committer that lets its commitment lapse is out for the round, others can still commit
+        let lottery_end = match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { end_time, .. } => end_time,
+            _ => panic!("Unexpected result from query"),
+        };
+        handle(&mut mocked_deps, mock_env("Robin", &[], lottery_end), commit_msg.clone()).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Robin", &[], lottery_end + 103), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This address let its commitment for this round lapse and can not commit again until the round is drawn"));
+        handle(&mut mocked_deps, mock_env("Batman", &[], lottery_end + 103), commit_msg).unwrap();
+    }
+
     //Stop contract
     //EmergencyRedeemFromStaking
-    //AllowWithdrawWhenStopped
+    //SetContractStatus WithdrawOnly
     //withdraw_excess
     #[test]
     fn emergency_stoppage_route_one() {
@@ -1705,7 +4490,7 @@ mod tests {
         //STOPPING CONTRACT
         ////ERROR CHECK
         let env = mock_env("haseeb", &[], 0);//for error checking
-        let msg = HandleMsg::StopContract {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None };
         let res = handle(&mut mocked_deps, env, msg);
         assert_eq!(res.unwrap_err(), StdError::generic_err(format!(
             "This is an admin command. Admin commands can only be run from admin address"
@@ -1713,10 +4498,10 @@ mod tests {
 
         ////NORMAL CHECK
         let env = mock_env("admin", &[], 0);
-        let msg = HandleMsg::StopContract {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None };
         let res = handle(&mut mocked_deps, env, msg);
         let res = match from_binary(&res.unwrap().data.unwrap()).unwrap() {
-            HandleAnswer::StopContract { status: Success, } => "success",
+            HandleAnswer::SetContractStatus { status: Success, } => "success",
             _ => panic!("Unexpected result from handle"),
         };
         assert_eq!(res, "success".to_string());
@@ -1727,17 +4512,19 @@ mod tests {
         let pending_rewards = supply_pool.pending_staking_rewards;
         let env = mock_env("admin", &[], 0);
         let msg = HandleMsg::EmergencyRedeemFromStaking {};
-        let _res = handle(&mut mocked_deps, env, msg);
+        //The contract is stopped, so the redeem is not queued
+        let res = handle(&mut mocked_deps, env, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
         let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
         assert_eq!(supply_pool.pending_staking_rewards, pending_rewards + Uint128(1000));
 
         //Allowing Withdraw When Stopped
         ////testing before allowed
-        let res = withdraw(&mut mocked_deps, mock_env("Batman", &[], 10), Option::from(Uint128(1000000000)));
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 10), HandleMsg::Withdraw { amount: Option::from(Uint128(1000000000)) });
         assert_eq!(res.unwrap_err(), StdError::generic_err(format!("This contract is stopped and this action is not allowed")));
 
         let env = mock_env("admin", &[], 0);
-        let msg = HandleMsg::AllowWithdrawWhenStopped {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::WithdrawOnly, reason: None };
         let _res = handle(&mut mocked_deps, env, msg);
 
         //TESTING DIFFERENT SCENARIOS OF WITHDRAW
@@ -1809,17 +4596,17 @@ mod tests {
         //stop contract
         //for error checking
         let env = mock_env("haseeb", &[], 0);
-        let msg = HandleMsg::StopContract {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None };
         let res = handle(&mut mocked_deps, env, msg);
         assert_eq!(res.unwrap_err(), StdError::generic_err(format!(
             "This is an admin command. Admin commands can only be run from admin address"
         )));
         //works fine and stop contract
         let env = mock_env("admin", &[], 0);
-        let msg = HandleMsg::StopContract {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None };
         let res = handle(&mut mocked_deps, env, msg);
         let res = match from_binary(&res.unwrap().data.unwrap()).unwrap() {
-            HandleAnswer::StopContract { status: Success, } => "success",
+            HandleAnswer::SetContractStatus { status: Success, } => "success",
             _ => panic!("Unexpected result from handle"),
         };
         assert_eq!(res, "success".to_string());
@@ -1830,7 +4617,9 @@ mod tests {
 
         let env = mock_env("admin", &[], 0);
         let msg = HandleMsg::EmergencyRedeemFromStaking {};
-        let _res = handle(&mut mocked_deps, env, msg);
+        //The contract is stopped, so the redeem is not queued
+        let res = handle(&mut mocked_deps, env, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
         let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
         assert_eq!(supply_pool.pending_staking_rewards, pending_rewards + Uint128(1000));
 
@@ -1859,13 +4648,13 @@ mod tests {
         let _res = redelegate_to_contract(&mut mocked_deps, env);
 
         let env = mock_env("non-admin", &[], 0);
-        let res = resume_contract(&mut mocked_deps, env);
+        let res = set_contract_status(&mut mocked_deps, env, ContractStatus::Normal, None);
         assert_eq!(res.unwrap_err(), StdError::generic_err(format!(
             "This is an admin command. Admin commands can only be run from admin address"
         )));
 
         let env = mock_env("admin", &[], 0);
-        let _res = resume_contract(&mut mocked_deps, env);
+        let _res = set_contract_status(&mut mocked_deps, env, ContractStatus::Normal, None);
 
         let res = withdraw(&mut mocked_deps, mock_env("Batman", &[], 10), Option::from(Uint128(10000000000)));
         assert_eq!(res.unwrap_err(), StdError::generic_err("Withdrawing more amount than Available tokens for withdraw"));
@@ -1893,57 +4682,616 @@ mod tests {
         let (_init_result, deps) = init_helper(None);
 
         // Deposit rewards on the staking contract
-        let mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
 
         let env = mock_env("not-admin", &[], 600);
-        let (mocked_deps, config) = config_helper(mocked_deps);
-        let res = check_if_admin(&config, &env.message.sender).unwrap_err();
+        let res = check_role(&mocked_deps.storage, Role::Owner, &env.message.sender).unwrap_err();
         assert_eq!(res, StdError::generic_err(
             "This is an admin command. Admin commands can only be run from admin address",
         ));
 
         let env = mock_env("admin", &[], 600);
-        let (mocked_deps, config) = config_helper(mocked_deps);
-
-        let res = check_if_admin(&config, &env.message.sender);
+        let res = check_role(&mocked_deps.storage, Role::Owner, &env.message.sender);
         assert_eq!(res, Ok(()));
 
         let env = mock_env("not-triggerer", &[], 600);
-        let (mocked_deps, config) = config_helper(mocked_deps);
-
-        let res = check_if_triggerer(&config, &env.message.sender).unwrap_err();
+        let res = check_role(&mocked_deps.storage, Role::Triggerer, &env.message.sender).unwrap_err();
         assert_eq!(res, StdError::generic_err(
             "This is an admin command. Admin commands can only be run from admin address and triggerer address",
         ));
 
         let env = mock_env("triggerer", &[], 600);
-        let (mocked_deps, config) = config_helper(mocked_deps);
-        let res = check_if_triggerer(&config, &env.message.sender);
+        let res = check_role(&mocked_deps.storage, Role::Triggerer, &env.message.sender);
         assert_eq!(res, Ok(()));
 
         //change admin
         let env = mock_env("not-admin", &[], 600);
-        let (mut mocked_deps, _) = config_helper(mocked_deps);
-        let res = change_admin(&mut mocked_deps, env, HumanAddr("triggerer".to_string())).unwrap_err();
+        let res = handle(&mut mocked_deps, env, HandleMsg::ProposeAdmin { address: HumanAddr("triggerer".to_string()) }).unwrap_err();
         assert_eq!(res, StdError::generic_err(
             "This is an admin command. Admin commands can only be run from admin address",
         ));
 
         let env = mock_env("admin", &[], 600);
-        let (mut mocked_deps, _config) = config_helper(mocked_deps);
-        let _res = change_admin(&mut mocked_deps, env, HumanAddr("someone".to_string())).unwrap();
-        let (mocked_deps, config) = config_helper(mocked_deps);
-        assert_eq!(config.admin, HumanAddr("someone".to_string()));
+        let _res = handle(&mut mocked_deps, env, HandleMsg::ProposeAdmin { address: HumanAddr("someone".to_string()) }).unwrap();
+        let _res = handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::AcceptAdmin {}).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Owner).unwrap(), vec![HumanAddr("someone".to_string())]);
+
+        let res = handle(&mut mocked_deps, mock_env("not-admin", &[], 600), HandleMsg::ProposeAdmin { address: HumanAddr("triggerer".to_string()) }).unwrap_err();
+        assert_eq!(res, StdError::generic_err(
+            "This is an admin command. Admin commands can only be run from admin address",
+        ));
+
+        let _res = handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::ProposeTriggerer { address: HumanAddr("someone".to_string()) }).unwrap();
+        let _res = handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::AcceptTriggerer {}).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Triggerer).unwrap(), vec![HumanAddr("someone".to_string())]);
+    }
+
+    #[test]
+    fn test_admin_handover() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        let pending = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>| {
+            match from_binary(&query(deps, QueryMsg::Roles {}).unwrap()).unwrap() {
+                QueryAnswer::Roles { pending, .. } => pending,
+                _ => panic!("unexpected"),
+            }
+        };
 
-        let (mut mocked_deps, _config) = config_helper(mocked_deps);
-        let res = change_admin(&mut mocked_deps, mock_env("not-admin", &[], 600), HumanAddr("triggerer".to_string())).unwrap_err();
+        //A cancelled proposal can not be accepted
+        handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::ProposeAdmin { address: HumanAddr("typo".to_string()) }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::CancelAdminProposal {}).unwrap();
+        assert_eq!(pending(&mocked_deps).admin, None);
+        let res = handle(&mut mocked_deps, mock_env("typo", &[], 600), HandleMsg::AcceptAdmin {}).unwrap_err();
+        assert_eq!(res, StdError::generic_err("This address has not been proposed"));
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::CancelAdminProposal {}).unwrap_err();
+        assert_eq!(res, StdError::generic_err("There is no pending proposal"));
+
+        //Nothing changes until the proposed address accepts, and only it can accept
+        handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::ProposeAdmin { address: HumanAddr("someone".to_string()) }).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Owner).unwrap(), vec![HumanAddr("admin".to_string())]);
+        assert_eq!(pending(&mocked_deps).admin, Some(HumanAddr("someone".to_string())));
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::AcceptAdmin {}).unwrap_err();
+        assert_eq!(res, StdError::generic_err("This address has not been proposed"));
+        handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) }).unwrap();
+        handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::AcceptAdmin {}).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Owner).unwrap(), vec![HumanAddr("someone".to_string())]);
+        assert_eq!(pending(&mocked_deps).admin, None);
+
+        //The outgoing admin's other roles move with it, the pauser that was not an owner keeps its role
+        match from_binary(&query(&mocked_deps, QueryMsg::Roles {}).unwrap()).unwrap() {
+            QueryAnswer::Roles { roles, .. } => {
+                assert_eq!(roles, vec![
+                    (Role::Owner, vec![HumanAddr("someone".to_string())]),
+                    (Role::Pauser, vec![HumanAddr("pauser".to_string()), HumanAddr("someone".to_string())]),
+                    (Role::ParameterManager, vec![HumanAddr("someone".to_string())]),
+                    (Role::FeeCollector, vec![HumanAddr("someone".to_string())]),
+                    (Role::Triggerer, vec![HumanAddr("triggerer".to_string())]),
+                ]);
+            }
+            _ => panic!("unexpected"),
+        }
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 600), HandleMsg::ProposeTriggerer { address: HumanAddr("robin".to_string()) }).unwrap_err();
         assert_eq!(res, StdError::generic_err(
             "This is an admin command. Admin commands can only be run from admin address",
         ));
 
-        let _res = change_triggerer(&mut mocked_deps, mock_env("someone", &[], 600), HumanAddr("someone".to_string())).unwrap();
+        //The triggerer is handed over the same way
+        handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::ProposeTriggerer { address: HumanAddr("robin".to_string()) }).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Triggerer).unwrap(), vec![HumanAddr("triggerer".to_string())]);
+        assert_eq!(pending(&mocked_deps).triggerer, Some(HumanAddr("robin".to_string())));
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 600), HandleMsg::AcceptTriggerer {}).unwrap_err();
+        assert_eq!(res, StdError::generic_err("This address has not been proposed"));
+        handle(&mut mocked_deps, mock_env("robin", &[], 600), HandleMsg::AcceptTriggerer {}).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Triggerer).unwrap(), vec![HumanAddr("robin".to_string())]);
+        let res = handle(&mut mocked_deps, mock_env("someone", &[], 600), HandleMsg::CancelTriggererProposal {}).unwrap_err();
+        assert_eq!(res, StdError::generic_err("There is no pending proposal"));
+    }
+
+    #[test]
+    fn test_roles() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        //The admin holds every role but the triggerer's
+        match from_binary(&query(&mocked_deps, QueryMsg::Roles {}).unwrap()).unwrap() {
+            QueryAnswer::Roles { roles, .. } => {
+                assert_eq!(roles, vec![
+                    (Role::Owner, vec![HumanAddr("admin".to_string())]),
+                    (Role::Pauser, vec![HumanAddr("admin".to_string())]),
+                    (Role::ParameterManager, vec![HumanAddr("admin".to_string())]),
+                    (Role::FeeCollector, vec![HumanAddr("admin".to_string())]),
+                    (Role::Triggerer, vec![HumanAddr("triggerer".to_string())]),
+                ]);
+            }
+            _ => panic!("unexpected"),
+        }
+
+        //Only an owner grants roles
+        let res = handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("".to_string()) });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::GrantRole { role: Role::ParameterManager, address: HumanAddr("manager".to_string()) }).unwrap();
+
+        //Each role reaches only its own handlers
+        let res = handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::ChangeUnbondingPeriod { period: 100 });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("manager", &[], 10), HandleMsg::ChangeUnbondingPeriod { period: 100 }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("manager", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("manager", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::WithdrawOnly, reason: None });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::Normal, reason: None });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This is an admin command. Admin commands can only be run from admin address"));
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::Normal, reason: None }).unwrap();
+
+        //A revoked address loses the role, the last owner can not be revoked
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::RevokeRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::RevokeRole { role: Role::Owner, address: HumanAddr("admin".to_string()) });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("The last owner can not be revoked"));
+    }
+
+    #[test]
+    fn test_timelock() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        handle(&mut mocked_deps, mock_env("admin", &[], 0), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+
+        //Queued, not applied
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 100), HandleMsg::ChangeStakingContract { address: HumanAddr("new_staking_contract".to_string()), contract_hash: "".to_string() });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 100), HandleMsg::ChangeStakingContract { address: HumanAddr("new_staking_contract".to_string()), contract_hash: "".to_string() }).unwrap();
+        match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::QueueAction { id, eta, .. } => {
+                assert_eq!(id, 0);
+                assert_eq!(eta, 86500);
+            }
+            _ => panic!("unexpected"),
+        }
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.staking_contract.address, HumanAddr("staking_contract".to_string()));
+        match from_binary(&query(&mocked_deps, QueryMsg::QueuedActions {}).unwrap()).unwrap() {
+            QueryAnswer::QueuedActions { actions, delay } => {
+                assert_eq!(delay, 86400);
+                assert_eq!(actions.len(), 1);
+                assert_eq!(actions[0].action, TimelockedAction::ChangeStakingContract { address: HumanAddr("new_staking_contract".to_string()), contract_hash: "".to_string() });
+            }
+            _ => panic!("unexpected"),
+        }
+
+        //Executable once the delay has passed, by an address holding the action's role
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 86499), HandleMsg::ExecuteAction { id: 0 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This action can not be executed before 86500"));
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 86500), HandleMsg::ExecuteAction { id: 0 });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::ExecuteAction { id: 0 }).unwrap();
+        assert_eq!(res.messages, vec![set_viewing_key_msg(
+            STAKING_VK.to_string(),
+            None,
+            RESPONSE_BLOCK_SIZE,
+            "".to_string(),
+            HumanAddr("new_staking_contract".to_string()),
+        ).unwrap()]);
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.staking_contract.address, HumanAddr("new_staking_contract".to_string()));
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::ExecuteAction { id: 0 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No queued action with this id"));
+
+        //Cancelled actions can not be executed
+        handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::WithdrawExcess {}).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 86500), HandleMsg::CancelAction { id: 1 });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::CancelAction { id: 1 }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 200000), HandleMsg::ExecuteAction { id: 1 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No queued action with this id"));
+    }
+
+    #[test]
+    fn test_emergency_redeem_timelock_exception() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+
+        //A running contract can not redeem everything
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::EmergencyRedeemFromStaking {});
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This action is only allowed while the contract is stopped"));
+
+        //Once stopped the redeem is not queued, the stake comes back in the same transaction
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 10), HandleMsg::EmergencyRedeemFromStaking {});
+        assert!(res.is_err());
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::EmergencyRedeemFromStaking {}).unwrap();
+        let redeem: CosmosMsg = WasmMsg::Execute {
+            contract_addr: HumanAddr("staking_contract".to_string()),
+            callback_code_hash: "".to_string(),
+            msg: to_binary(&LPStakingHandleMsg::Redeem { amount: supply_pool.total_tokens_staked + supply_pool.total_rewards_restaked }).unwrap(),
+            send: vec![],
+        }.into();
+        assert_eq!(res.messages, vec![redeem]);
+        match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::EmergencyRedeemFromStaking { status: Success } => {}
+            _ => panic!("unexpected"),
+        }
+        match from_binary(&query(&mocked_deps, QueryMsg::QueuedActions {}).unwrap()).unwrap() {
+            QueryAnswer::QueuedActions { actions, .. } => assert!(actions.is_empty()),
+            _ => panic!("unexpected"),
+        }
+
+        //The other timelocked actions are still queued on a stopped contract
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::WithdrawExcess {}).unwrap();
+        match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::QueueAction { id, eta, .. } => assert_eq!((id, eta), (0, 10 + DEFAULT_TIMELOCK_DELAY)),
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_contract_status() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        let deposit_msg = HandleMsg::Receive {
+            sender: HumanAddr("Batman".to_string()),
+            from: HumanAddr("Batman".to_string()),
+            amount: Uint128(1000000),
+            msg: to_binary(&HandleMsg::Deposit {}).unwrap(),
+        };
+        let commit_msg = HandleMsg::CommitDraw { entropy_hash: Binary::from(sha_256("entropy".as_bytes()).to_vec()) };
+
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::DepositsPaused, reason: None });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::DepositsPaused, reason: Some("Migrating".to_string()) }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::ContractStatus {}).unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status, reason } => {
+                assert_eq!(status, ContractStatus::DepositsPaused);
+                assert_eq!(reason, Some("Migrating".to_string()));
+            }
+            _ => panic!("unexpected"),
+        }
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 10), deposit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Deposits are paused"));
+        //Draws go on, this one only fails because the round has not ended
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 10), commit_msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Lottery end height is in the future"));
+
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::DrawsPaused, reason: None }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("triggerer", &[], 86400), commit_msg);
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Draws are paused"));
+        handle(&mut mocked_deps, mock_env("sefi", &[], 10), deposit_msg.clone()).unwrap();
+
+        //The ChangeStakingContractFlow needs a stopped contract
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::EmergencyRedeemFromStaking {});
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This action is only allowed while the contract is stopped"));
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 10), deposit_msg);
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This contract is stopped and this action is not allowed"));
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::EmergencyRedeemFromStaking {}).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_staking_contract() {
+        let (_init_result, deps) = init_helper(Some(800000000));
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+        let new_contract = SecretContract {
+            address: HumanAddr("new_staking_contract".to_string()),
+            contract_hash: "".to_string(),
+        };
+
+        //Timelocked like the other staking contract changes, but the contract keeps running
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 100), HandleMsg::MigrateStakingContract { new_contract: new_contract.clone() });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 100), HandleMsg::MigrateStakingContract { new_contract: new_contract.clone() }).unwrap();
+
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        let amount = supply_pool.total_tokens_staked + supply_pool.total_rewards_restaked;
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::ExecuteAction { id: 0 }).unwrap();
+        //Redeem from the old contract, viewing key and deposit on the new one
+        assert_eq!(res.messages.len(), 3);
+        match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::MigrateStakingContract { amount: migrated, .. } => assert_eq!(migrated, amount),
+            _ => panic!("unexpected"),
+        }
+        let (mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.staking_contract, new_contract);
+        let (mut mocked_deps, supply_pool_after) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool_after.pending_staking_rewards, supply_pool.pending_staking_rewards + Uint128(1000));
+
+        match from_binary(&query(&mocked_deps, QueryMsg::StakingMigrations {}).unwrap()).unwrap() {
+            QueryAnswer::StakingMigrations { migrations } => {
+                assert_eq!(migrations.len(), 1);
+                assert_eq!(migrations[0].old_contract, HumanAddr("staking_contract".to_string()));
+                assert_eq!(migrations[0].new_contract, HumanAddr("new_staking_contract".to_string()));
+                assert_eq!(migrations[0].amount, amount);
+            }
+            _ => panic!("unexpected"),
+        }
+
+        //Migrating to the contract the pool already stakes in fails
+        handle(&mut mocked_deps, mock_env("admin", &[], 86500), HandleMsg::MigrateStakingContract { new_contract }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 172900), HandleMsg::ExecuteAction { id: 1 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("The pool already stakes in this contract"));
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let (_init_result, deps) = init_helper(Some(800000000));
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+
+        //Withdrawals move from staked to pending, and leave the pool once paid out
+        trigger_withdraw(&mut mocked_deps, mock_env("Batman", &[], 10), Some(Uint128(40000000))).unwrap();
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.total_pending_withdrawals, Uint128(40000000));
+        withdraw(&mut mocked_deps, mock_env("Batman", &[], 110), Some(Uint128(10000000))).unwrap();
+        let (mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.total_pending_withdrawals, Uint128(30000000));
+
+        match from_binary(&query(&mocked_deps, QueryMsg::Reconcile {}).unwrap()).unwrap() {
+            QueryAnswer::Reconcile { token_balance, staked_balance, user_liabilities, fees, prize_pool, surplus, shortfall } => {
+                assert_eq!(token_balance, Uint128(2000000000));
+                assert_eq!(staked_balance, Uint128(1500000000));
+                assert_eq!(user_liabilities, supply_pool.total_tokens_staked + supply_pool.total_pending_withdrawals);
+                assert_eq!(fees, supply_pool.triggering_cost + supply_pool.protocol_fees + supply_pool.charity_fees);
+                assert_eq!(prize_pool, supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards);
+                let liabilities = user_liabilities + fees + prize_pool;
+                assert_eq!(surplus, (token_balance + staked_balance - liabilities).unwrap());
+                assert_eq!(shortfall, Uint128(0));
+            }
+            _ => panic!("unexpected"),
+        }
+
+        //Less held than owed is reported as a shortfall
+        let mocked_deps = mocked_deps.change_querier(|_| BalanceMockQuerier { token_balance: Uint128(10000000), staked_balance: Uint128(400000000) });
+        match from_binary(&query(&mocked_deps, QueryMsg::Reconcile {}).unwrap()).unwrap() {
+            QueryAnswer::Reconcile { token_balance, staked_balance, surplus, shortfall, .. } => {
+                assert_eq!((token_balance, staked_balance), (Uint128(10000000), Uint128(400000000)));
+                let liabilities = supply_pool.total_tokens_staked + supply_pool.total_pending_withdrawals
+                    + supply_pool.triggering_cost + supply_pool.protocol_fees + supply_pool.charity_fees
+                    + supply_pool.total_rewards_restaked + supply_pool.pending_staking_rewards;
+                assert_eq!(surplus, Uint128(0));
+                assert_eq!(shortfall, (liabilities - Uint128(410000000)).unwrap());
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_invariant_circuit_breaker() {
+        let (_init_result, deps) = init_helper(Some(800000000));
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        let deposit_msg = HandleMsg::Receive {
+            sender: HumanAddr("Batman".to_string()),
+            from: HumanAddr("Batman".to_string()),
+            amount: Uint128(1000000),
+            msg: to_binary(&HandleMsg::Deposit {}).unwrap(),
+        };
+
+        //Consistent accounting leaves the contract running
+        handle(&mut mocked_deps, mock_env("sefi", &[], 10), deposit_msg.clone()).unwrap();
+        handle(&mut mocked_deps, mock_env("Batman", &[], 20), HandleMsg::TriggerWithdraw { amount: Some(Uint128(400000)) }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 30), HandleMsg::Withdraw { amount: None }).unwrap();
+        assert!(res.log.is_empty());
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.status, ContractStatus::Normal);
+
+        //A total drifting away from the users' records stops the contract with withdrawals allowed
+        let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut mocked_deps.storage);
+        let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, MockStorage>>::attach(&mut supply_pool_prefixed);
+        let mut supply_pool: SupplyPool = supply_store.load(SUPPLY_POOL_KEY).unwrap();
+        supply_pool.total_tokens_staked += Uint128(5);
+        supply_store.store(SUPPLY_POOL_KEY, &supply_pool).unwrap();
+
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 40), deposit_msg.clone()).unwrap();
+        let reason = "Accounting invariant failed: users' principal 1600000 does not match total tokens staked 1600005".to_string();
+        assert_eq!(res.log, vec![log("invariant_failed", reason.clone())]);
+        //Everything the staking contract holds is redeemed with the stop, so withdrawals can be paid
+        let redeem: CosmosMsg = WasmMsg::Execute {
+            contract_addr: HumanAddr("staking_contract".to_string()),
+            callback_code_hash: "".to_string(),
+            msg: to_binary(&LPStakingHandleMsg::Redeem { amount: Uint128(1500000000) }).unwrap(),
+            send: vec![],
+        }.into();
+        assert_eq!(res.messages.last(), Some(&redeem));
+        match from_binary(&query(&mocked_deps, QueryMsg::ContractStatus {}).unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status, reason: status_reason } => {
+                assert_eq!(status, ContractStatus::WithdrawOnly);
+                assert_eq!(status_reason, Some(reason));
+            }
+            _ => panic!("unexpected"),
+        }
+        let res = handle(&mut mocked_deps, mock_env("sefi", &[], 50), deposit_msg);
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This contract is stopped and this action is not allowed"));
+        handle(&mut mocked_deps, mock_env("Batman", &[], 50), HandleMsg::Withdraw { amount: Some(Uint128(100000)) }).unwrap();
+
+        //Once the redeem has gone through, unbonding takes nothing more from the staking contract
+        let mut mocked_deps = mocked_deps.change_querier(|_| BalanceMockQuerier { token_balance: Uint128(2000000000), staked_balance: Uint128(0) });
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 50), HandleMsg::TriggerWithdraw { amount: Some(Uint128(100000)) }).unwrap();
+        assert!(!res.messages.iter().any(|msg| match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => contract_addr == &HumanAddr("staking_contract".to_string()),
+            _ => false,
+        }));
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 50), HandleMsg::Withdraw { amount: Some(Uint128(100000)) }).unwrap();
+        assert_eq!(res.messages, vec![transfer_msg(
+            HumanAddr("Batman".to_string()),
+            Uint128(100000),
+            None,
+            RESPONSE_BLOCK_SIZE,
+            "".to_string(),
+            HumanAddr("sefi".to_string()),
+        ).unwrap()]);
+
+        //Fully stopped, nothing moves
+        handle(&mut mocked_deps, mock_env("admin", &[], 60), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 60), HandleMsg::TriggerWithdraw { amount: Some(Uint128(100000)) });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This contract is stopped and this action is not allowed"));
+
+        //A staking contract that can not be queried still lets the breaker stop the contract, without a redeem
+        let mut mocked_deps = mocked_init_helper(init_msg_helper());
+        handle(&mut mocked_deps, mock_env("sefi", &[], 10), deposit_msg_helper("Batman", 1000000)).unwrap();
+        let (mut mocked_deps, mut supply_pool) = supply_pool_helper(mocked_deps);
+        supply_pool.total_tokens_staked += Uint128(5);
+        let mut supply_pool_prefixed = PrefixedStorage::multilevel(&[SUPPLY_POOL_KEY_PREFIX], &mut mocked_deps.storage);
+        let mut supply_store = TypedStoreMut::<SupplyPool, PrefixedStorage<'_, MockStorage>>::attach(&mut supply_pool_prefixed);
+        supply_store.store(SUPPLY_POOL_KEY, &supply_pool).unwrap();
+
+        let mut mocked_deps = mocked_deps.change_querier(|_| FailingMockQuerier {});
+        let res = handle(&mut mocked_deps, mock_env("Batman", &[], 20), HandleMsg::SetViewingKey { key: "key".to_string(), padding: None }).unwrap();
+        assert!(res.messages.is_empty());
+        match from_binary(&query(&mocked_deps, QueryMsg::ContractStatus {}).unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status, reason } => {
+                assert_eq!(status, ContractStatus::WithdrawOnly);
+                assert!(reason.unwrap().contains("staking contract query failed"));
+            }
+            _ => panic!("unexpected"),
+        }
+        handle(&mut mocked_deps, mock_env("Batman", &[], 30), HandleMsg::Withdraw { amount: Some(Uint128(100000)) }).unwrap();
+    }
+
+    #[test]
+    fn test_query_config() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ProposeAdmin { address: HumanAddr("new_admin".to_string()) }).unwrap();
+
+        let res = query(&mocked_deps, QueryMsg::Config {}).unwrap();
         let (_mocked_deps, config) = config_helper(mocked_deps);
-        assert_eq!(config.triggerer, HumanAddr("someone".to_string()));
+        //The answer never carries the contract's secrets
+        let raw = String::from_utf8(res.as_slice().to_vec()).unwrap();
+        assert!(!raw.contains("viewing_key"));
+        assert!(!raw.contains("prng_seed"));
+        match from_binary(&res).unwrap() {
+            QueryAnswer::Config { version, admins, triggerers, pending_handover, token, staking_contract, own_addr, fees, status, .. } => {
+                assert_eq!(version, env!("CARGO_PKG_VERSION"));
+                assert_eq!(admins, vec![HumanAddr("admin".to_string())]);
+                assert_eq!(triggerers, vec![HumanAddr("triggerer".to_string())]);
+                assert_eq!(pending_handover.admin, Some(HumanAddr("new_admin".to_string())));
+                assert_eq!(token, config.token);
+                assert_eq!(staking_contract, config.staking_contract);
+                assert_eq!(own_addr, config.own_addr);
+                assert_eq!(fees, config.fees);
+                assert_eq!(status, ContractStatus::Normal);
+            }
+            _ => panic!("unexpected"),
+        }
+    }
+
+    #[test]
+    fn test_token_queries() {
+        let (_init_result, deps) = init_helper(None);
+        let mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        match from_binary(&query(&mocked_deps, QueryMsg::TokenInfo {}).unwrap()).unwrap() {
+            QueryAnswer::TokenInfo { name, symbol, decimals, total_supply } => {
+                assert_eq!(name, "Secret Finance".to_string());
+                assert_eq!(symbol, "SEFI".to_string());
+                assert_eq!(decimals, 6);
+                assert_eq!(total_supply, Some(Uint128(1000000000)));
+            }
+            _ => panic!("unexpected"),
+        }
+        let (mocked_deps, config) = config_helper(mocked_deps);
+        match from_binary(&query(&mocked_deps, QueryMsg::IncentivizedToken {}).unwrap()).unwrap() {
+            QueryAnswer::IncentivizedToken { token } => assert_eq!(token, config.token),
+            _ => panic!("unexpected"),
+        }
+
+        //Public queries never reach the viewing key checks
+        let res = authenticated_queries(&mocked_deps, QueryMsg::Jackpot {});
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This query does not take a viewing key"));
+    }
+
+    #[test]
+    fn test_multisig() {
+        let mut init_msg = init_msg_helper();
+        init_msg.multisig = Some(Multisig { signers: vec![HumanAddr("signer1".to_string())], threshold: 2 });
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("Multisig threshold must be between 1 and the number of signers"));
+
+        init_msg.multisig = Some(Multisig {
+            signers: vec![HumanAddr("signer1".to_string()), HumanAddr("signer2".to_string()), HumanAddr("signer3".to_string())],
+            threshold: 2,
+        });
+        let (_init_result, deps) = init_with_msg_helper(None, init_msg);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+
+        //Admin actions become proposals, only a role holder can open one
+        let res = handle(&mut mocked_deps, mock_env("signer1", &[], 10), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) });
+        assert!(res.is_err());
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::GrantRole { role: Role::Pauser, address: HumanAddr("pauser".to_string()) }).unwrap();
+        match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::Propose { proposal_id, .. } => assert_eq!(proposal_id, 0),
+            _ => panic!("unexpected"),
+        }
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Pauser).unwrap(), vec![HumanAddr("admin".to_string())]);
+
+        //Distinct signers approve, the threshold'th approval runs it
+        let res = handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::Approve { proposal_id: 0 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Only multisig signers can approve proposals"));
+        handle(&mut mocked_deps, mock_env("signer1", &[], 10), HandleMsg::Approve { proposal_id: 0 }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("signer1", &[], 10), HandleMsg::Approve { proposal_id: 0 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("This signer has already approved the proposal"));
+        match from_binary(&query(&mocked_deps, QueryMsg::Proposals {}).unwrap()).unwrap() {
+            QueryAnswer::Proposals { proposals, .. } => {
+                assert_eq!(proposals.len(), 1);
+                assert_eq!(proposals[0].approvals, vec![HumanAddr("signer1".to_string())]);
+            }
+            _ => panic!("unexpected"),
+        }
+        handle(&mut mocked_deps, mock_env("signer2", &[], 10), HandleMsg::Approve { proposal_id: 0 }).unwrap();
+        assert_eq!(load_role_members(&mocked_deps.storage, Role::Pauser).unwrap(), vec![HumanAddr("admin".to_string()), HumanAddr("pauser".to_string())]);
+        let res = handle(&mut mocked_deps, mock_env("signer3", &[], 10), HandleMsg::Approve { proposal_id: 0 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("No open proposal with this id"));
+
+        //Proposals can be withdrawn by their proposer
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ProposeAdmin { address: HumanAddr("someone".to_string()) }).unwrap();
+        let res = handle(&mut mocked_deps, mock_env("signer1", &[], 10), HandleMsg::CancelProposal { proposal_id: 1 });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::CancelProposal { proposal_id: 1 }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::Proposals {}).unwrap()).unwrap() {
+            QueryAnswer::Proposals { proposals, .. } => assert!(proposals.is_empty()),
+            _ => panic!("unexpected"),
+        }
+
+        //A single owner can not change the fees, nor resume the contract, without the signers
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        let mut fees = config.fees.clone();
+        fees.protocol_fee_bps = 2000;
+        fees.treasury = HumanAddr("admin".to_string());
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangeFeeSchedule { fees: fees.clone() }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, .. } => assert_eq!(changes.fees, None),
+            _ => panic!("unexpected"),
+        }
+        handle(&mut mocked_deps, mock_env("signer1", &[], 10), HandleMsg::Approve { proposal_id: 2 }).unwrap();
+        handle(&mut mocked_deps, mock_env("signer3", &[], 10), HandleMsg::Approve { proposal_id: 2 }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, .. } => assert_eq!(changes.fees, Some(fees)),
+            _ => panic!("unexpected"),
+        }
+
+        //The Pauser still stops the contract on its own
+        handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::Normal, reason: None }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::ContractStatus {}).unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status, .. } => assert_eq!(status, ContractStatus::FullyStopped),
+            _ => panic!("unexpected"),
+        }
+
+        //but a paused status is less restrictive than a stop, so it is a resume as well
+        let res = handle(&mut mocked_deps, mock_env("pauser", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::DepositsPaused, reason: None });
+        assert!(res.is_err());
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::SetContractStatus { status: ContractStatus::DepositsPaused, reason: None }).unwrap();
+        match from_binary(&query(&mocked_deps, QueryMsg::ContractStatus {}).unwrap()).unwrap() {
+            QueryAnswer::ContractStatus { status, .. } => assert_eq!(status, ContractStatus::FullyStopped),
+            _ => panic!("unexpected"),
+        }
     }
 
     #[test]
@@ -1956,17 +5304,17 @@ mod tests {
 
         deposit(&mut deps, mock_env("sefi", &[], 600), HumanAddr("Batman".to_string()), Uint128(500000000)).unwrap();
 
-        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::StopContract {});
+        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
 
         let _res = handle(&mut deps, mock_env("Batman", &[], 600), HandleMsg::TriggerWithdraw { amount: Option::from(Uint128(500000000)) });
 
-        let _res = handle(&mut deps, mock_env("Batman", &[], 600), HandleMsg::StopContract {});
+        let _res = handle(&mut deps, mock_env("Batman", &[], 600), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
 
         let _res = handle(&mut deps, mock_env("Batman", &[], 600), HandleMsg::TriggerWithdraw { amount: Option::from(Uint128(500000000)) });
 
-        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::StopContract {});
+        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None });
 
-        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::ResumeContract {});
+        let _res = handle(&mut deps, mock_env("admin", &[], 600), HandleMsg::SetContractStatus { status: ContractStatus::Normal, reason: None });
 
         let _res = handle(&mut deps, mock_env("Batman", &[], 10000000), HandleMsg::Withdraw { amount: Option::from(Uint128(500000000)) });
     }
@@ -1987,11 +5335,17 @@ mod tests {
 
         let handlemsg = HandleMsg::ChangeTriggererShare { percentage: 200 };
         let _res = handle(&mut mocked_deps, mock_env("admin", &[], 10), handlemsg);
-        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], lottery.end_time)).unwrap();
+        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], lottery.end_time), &[]).unwrap();
         // println!("{:?}",_response);
 
+        //The round already accruing is shared at the old percentage
+        let (mut mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
+        assert_eq!(supply_pool.triggering_cost, Uint128(30));
+
+        deposit(&mut mocked_deps, mock_env("sefi", &[], lottery.end_time), HumanAddr("batman".to_string()), Uint128(5000000000)).unwrap();
+        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], lottery.end_time + lottery.duration), &[]).unwrap();
         let (_mocked_deps, supply_pool) = supply_pool_helper(mocked_deps);
-        assert_eq!(supply_pool.triggering_cost, Uint128(60));
+        assert_eq!(supply_pool.triggering_cost, Uint128(70));
     }
 
     #[test]
@@ -2007,20 +5361,79 @@ mod tests {
         let handlemsg = HandleMsg::ChangeLotteryDuration { duration: 100 };
         let _res = handle(&mut mocked_deps, mock_env("admin", &[], 10), handlemsg);
 
+        //The current round keeps its duration
         let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
         let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
         let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
 
-        assert_eq!(a_lottery.duration, 100);
+        assert_eq!(a_lottery.duration, 86400);
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, .. } => assert_eq!(changes.duration, Some(100)),
+            _ => panic!("Unexpected result from query"),
+        }
 
         let lottery_end = a_lottery.end_time;
 
-        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time)).unwrap();
+        let _response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]).unwrap();
         let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
         let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
         let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
         assert_eq!(a_lottery.start_time, lottery_end);
         assert_eq!(a_lottery.end_time, lottery_end + 100);
+        assert_eq!(a_lottery.duration, 100);
+        match from_binary(&query(&mocked_deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+            QueryAnswer::PendingConfigChanges { changes, .. } => assert_eq!(changes, PendingConfigChanges::default()),
+            _ => panic!("Unexpected result from query"),
+        }
+    }
+
+    #[test]
+    fn test_pending_config_changes() {
+        let mocked_deps = mocked_init_helper(init_msg_helper());
+        let (mut mocked_deps, initial_config) = config_helper(mocked_deps);
+
+        //Several changes are staged together, a later change of the same field replaces the earlier one
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangeTriggererShare { percentage: 200 }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangeLotteryDuration { duration: 100 }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangeUnbondingPeriod { period: 50 }).unwrap();
+        handle(&mut mocked_deps, mock_env("admin", &[], 10), HandleMsg::ChangeTriggererShare { percentage: 300 }).unwrap();
+        let pending = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>| {
+            match from_binary(&query(deps, QueryMsg::PendingConfigChanges {}).unwrap()).unwrap() {
+                QueryAnswer::PendingConfigChanges { changes, round_id, draw_time } => (changes, round_id, draw_time),
+                _ => panic!("Unexpected result from query"),
+            }
+        };
+        let (changes, round_id, draw_time) = pending(&mocked_deps);
+        assert_eq!(changes.fees.clone().unwrap().triggerer_fee_bps, 300);
+        assert_eq!((changes.duration, changes.unbonding_period), (Some(100), Some(50)));
+        assert_eq!((round_id, draw_time), (1, 86400));
+
+        //A round that rolls over is not drawn, the changes wait for the next draw
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 86400), &[]).unwrap();
+        match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { rolled_over, .. } => assert!(rolled_over),
+            _ => panic!("Unexpected result from handle"),
+        }
+        assert_eq!(pending(&mocked_deps), (changes.clone(), 2, 172800));
+        let (mut mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.fees, initial_config.fees);
+        assert_eq!(config.unbonding_period, initial_config.unbonding_period);
+
+        //Nor does a commitment that expires without a reveal
+        handle(&mut mocked_deps, mock_env("sefi", &[], 100000), deposit_msg_helper("Batman", 100000000)).unwrap();
+        let secret = Binary::from("Why so serious?".as_bytes());
+        let commit_msg = HandleMsg::CommitDraw { entropy_hash: Binary(sha_256(&secret.0).to_vec()) };
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 172800), commit_msg.clone()).unwrap();
+        assert_eq!(pending(&mocked_deps), (changes.clone(), 2, 172800));
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 172903), commit_msg).unwrap();
+        assert_eq!(pending(&mocked_deps), (changes, 2, 172800));
+
+        //All of them apply together once the round is drawn
+        handle(&mut mocked_deps, mock_env("triggerer", &[], 172905), HandleMsg::RevealDraw { entropy: secret }).unwrap();
+        assert_eq!(pending(&mocked_deps), (PendingConfigChanges::default(), 3, 173005));
+        let (_mocked_deps, config) = config_helper(mocked_deps);
+        assert_eq!(config.fees.triggerer_fee_bps, 300);
+        assert_eq!(config.unbonding_period, 50);
     }
 
     //Testing Queries
@@ -2047,6 +5460,80 @@ mod tests {
         assert_eq!(a_lottery.duration, duration);
     }
 
+    #[test]
+    fn test_round_schedule() {
+        let mut init_msg = init_msg_helper();
+        init_msg.duration = Some(0);
+        let (init_result, _deps) = init_with_msg_helper(None, init_msg.clone());
+        assert_eq!(init_result.unwrap_err(), StdError::generic_err("Round duration must be greater than zero"));
+
+        //Hourly rounds ending at 1000 seconds past the hour
+        init_msg.duration = Some(3600);
+        init_msg.schedule_anchor = Some(1000);
+        let (_init_result, deps) = init_with_msg_helper(None, init_msg);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        let lottery_info = |deps: &Extern<MockStorage, MockApi, MyMockQuerier>| {
+            match from_binary(&query(deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+                QueryAnswer::LotteryInfo { start_time, end_time, schedule_anchor, .. } => {
+                    assert_eq!(schedule_anchor, Some(1000));
+                    (start_time, end_time)
+                }
+                _ => panic!("Unexpected result from query"),
+            }
+        };
+        assert_eq!(lottery_info(&mocked_deps), (0, 1000));
+
+        //A draw triggered late keeps to the schedule
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 1500), &[]).unwrap();
+        assert_eq!(lottery_info(&mocked_deps), (1000, 4600));
+        claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], 12000), &[]).unwrap();
+        assert_eq!(lottery_info(&mocked_deps), (11800, 15400));
+    }
+
+    #[test]
+    fn test_query_round_info() {
+        let (_init_result, deps) = init_helper(None);
+        let mut mocked_deps = deps.change_querier(|_| MyMockQuerier {});
+        mocked_deps = deposit_helper(mocked_deps, mock_env("sefi", &[], 0));
+
+        let res = query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Round 1 has not been drawn yet"));
+
+        let lottery_prefixed = ReadonlyPrefixedStorage::multilevel(&[LOTTERY_KEY_PREFIX], &mocked_deps.storage);
+        let lottery_store = TypedStore::<Lottery, ReadonlyPrefixedStorage<'_, MockStorage>>::attach(&lottery_prefixed);
+        let a_lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
+        assert_eq!(a_lottery.round_id, 1);
+
+        let response = claim_rewards(&mut mocked_deps, mock_env("triggerer", &[], a_lottery.end_time), &[]).unwrap();
+        let winners = match from_binary(&response.data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: Success, winners, .. } => winners,
+            _ => panic!("Unexpected result from handle"),
+        };
+
+        let round = match from_binary(&query(&mocked_deps, QueryMsg::RoundInfo { round_id: 1 }).unwrap()).unwrap() {
+            QueryAnswer::RoundInfo { round } => round,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round.round_id, 1);
+        assert_eq!(round.start_time, a_lottery.start_time);
+        assert_eq!(round.end_time, a_lottery.end_time);
+        assert_eq!(round.participants, 9);
+        assert_eq!(round.total_weight, Uint128(1008000000));
+        assert_eq!(round.winners, winners);
+        assert_eq!(round.prize, Uint128(10890));
+        assert_eq!(round.triggerer_fee, Uint128(110));
+        assert_eq!(round.status, RoundStatus::Drawn);
+
+        //The next round is numbered on and has no record until it is drawn
+        let round_id = match from_binary(&query(&mocked_deps, QueryMsg::LotteryInfo {}).unwrap()).unwrap() {
+            QueryAnswer::LotteryInfo { round_id, .. } => round_id,
+            _ => panic!("Unexpected result from query"),
+        };
+        assert_eq!(round_id, 2);
+        let res = query(&mocked_deps, QueryMsg::RoundInfo { round_id: 2 });
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Round 2 has not been drawn yet"));
+    }
+
     #[test]
     fn test_available_funds() {
         let (_init_result, deps) = init_helper(None);
@@ -2080,30 +5567,32 @@ mod tests {
         let query_balance_msg = QueryMsg::AvailableTokensForWithdrawl {
             address: HumanAddr("batman".to_string()),
             key: vk.clone().0,
+            time: 601,
         };
         let query_response = query(&mocked_deps, query_balance_msg).unwrap();
         let balance = match from_binary(&query_response).unwrap() {
-            QueryAnswer::AvailableTokensForWithdrawl { amount } => amount,
+            QueryAnswer::AvailableTokensForWithdrawl { amount, .. } => amount,
             _ => panic!("Unexpected result from query"),
         };
         assert_eq!(balance, Uint128(0));
 
         //IN CASE THE CONTRACT IS STOPPED
         let env = mock_env("admin", &[], 0);
-        let msg = HandleMsg::StopContract {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::FullyStopped, reason: None };
         let _res = handle(&mut mocked_deps, env, msg);
 
         let env = mock_env("admin", &[], 0);
-        let msg = HandleMsg::AllowWithdrawWhenStopped {};
+        let msg = HandleMsg::SetContractStatus { status: ContractStatus::WithdrawOnly, reason: None };
         let _res = handle(&mut mocked_deps, env, msg);
 
         let query_balance_msg = QueryMsg::AvailableTokensForWithdrawl {
             address: HumanAddr("batman".to_string()),
             key: vk.0,
+            time: 601,
         };
         let query_response = query(&mocked_deps, query_balance_msg).unwrap();
         let balance = match from_binary(&query_response).unwrap() {
-            QueryAnswer::AvailableTokensForWithdrawl { amount } => amount,
+            QueryAnswer::AvailableTokensForWithdrawl { amount, .. } => amount,
             _ => panic!("Unexpected result from query"),
         };
         assert_eq!(balance, Uint128(5000000000));
@@ -2175,15 +5664,15 @@ mod tests {
         let lottery: Lottery = lottery_store.load(LOTTERY_KEY).unwrap();
 
         let env = mock_env("triggerer", &[], lottery.end_time);
-        let _res2 = claim_rewards(&mut mocked_deps, env);
+        let _res2 = claim_rewards(&mut mocked_deps, env, &[]);
         let env = mock_env("triggerer", &[], lottery.end_time + lottery.duration);
-        let _res3 = claim_rewards(&mut mocked_deps, env);
+        let _res3 = claim_rewards(&mut mocked_deps, env, &[]);
         let env = mock_env("triggerer", &[], lottery.end_time + lottery.duration + lottery.duration);
-        let _res4 = claim_rewards(&mut mocked_deps, env);
+        let _res4 = claim_rewards(&mut mocked_deps, env, &[]);
         let env = mock_env("triggerer", &[], lottery.end_time + lottery.duration + lottery.duration + lottery.duration);
-        let res5 = claim_rewards(&mut mocked_deps, env);
-        let _winner5 = match from_binary(&res5.unwrap().data.unwrap()).unwrap() {
-            HandleAnswer::ClaimRewards { status: _, winner } => winner,
+        let res5 = claim_rewards(&mut mocked_deps, env, &[]);
+        let _winners5 = match from_binary(&res5.unwrap().data.unwrap()).unwrap() {
+            HandleAnswer::ClaimRewards { status: _, winners, .. } => winners,
             _ => panic!("Unexpected result from handle"),
         };
         let _res: QueryAnswer = from_binary(&query_past_results(&mocked_deps).unwrap()).unwrap();
@@ -2263,7 +5752,7 @@ mod tests {
 
         let env = mock_env("triggerer", &[], 1000000);
 
-        claim_rewards(&mut mocked_deps, env.clone()).unwrap();
+        claim_rewards(&mut mocked_deps, env.clone(), &[]).unwrap();
 
         let no_vk_yet_query_msg = QueryMsg::Balance {
             address: HumanAddr("batman".to_string()),
@@ -0,0 +1,173 @@
+use cosmwasm_std::{ReadonlyStorage, StdError, StdResult, Storage};
+
+//Cumulative sums over slots 1..=FENWICK_TREE_SIZE kept in storage, one key per non-zero node.
+//Every operation touches at most log2(FENWICK_TREE_SIZE) + 1 nodes, whatever the number of slots in use.
+pub const FENWICK_TREE_SIZE: u64 = 1 << 24;
+
+fn read_node<S: ReadonlyStorage>(storage: &S, node: u64) -> u128 {
+    match storage.get(&node.to_be_bytes()) {
+        Some(bytes) => {
+            let mut value = [0u8; 16];
+            value.copy_from_slice(&bytes);
+            u128::from_be_bytes(value)
+        }
+        None => 0,
+    }
+}
+
+fn write_node<S: Storage>(storage: &mut S, node: u64, value: u128) {
+    if value == 0 {
+        storage.remove(&node.to_be_bytes());
+    } else {
+        storage.set(&node.to_be_bytes(), &value.to_be_bytes());
+    }
+}
+
+fn check_slot(slot: u64) -> StdResult<()> {
+    if slot == 0 || slot > FENWICK_TREE_SIZE {
+        return Err(StdError::generic_err(format!("Slot must be between 1 and {}", FENWICK_TREE_SIZE)));
+    }
+    Ok(())
+}
+
+pub fn add<S: Storage>(storage: &mut S, slot: u64, amount: u128) -> StdResult<()> {
+    check_slot(slot)?;
+    let mut node = slot;
+    while node <= FENWICK_TREE_SIZE {
+        let value = read_node(storage, node).checked_add(amount)
+            .ok_or_else(|| StdError::generic_err("Cumulative index overflow"))?;
+        write_node(storage, node, value);
+        node += node & node.wrapping_neg();
+    }
+    Ok(())
+}
+
+pub fn sub<S: Storage>(storage: &mut S, slot: u64, amount: u128) -> StdResult<()> {
+    check_slot(slot)?;
+    let mut node = slot;
+    while node <= FENWICK_TREE_SIZE {
+        let value = read_node(storage, node).checked_sub(amount)
+            .ok_or_else(|| StdError::generic_err("Cumulative index underflow"))?;
+        write_node(storage, node, value);
+        node += node & node.wrapping_neg();
+    }
+    Ok(())
+}
+
+//Sum of slots 1..=slot, prefix_sum(0) is 0
+pub fn prefix_sum<S: ReadonlyStorage>(storage: &S, slot: u64) -> u128 {
+    let mut node = slot.min(FENWICK_TREE_SIZE);
+    let mut sum = 0u128;
+    while node > 0 {
+        sum += read_node(storage, node);
+        node -= node & node.wrapping_neg();
+    }
+    sum
+}
+
+pub fn total<S: ReadonlyStorage>(storage: &S) -> u128 {
+    read_node(storage, FENWICK_TREE_SIZE)
+}
+
+fn net(plus: u128, minus: u128) -> StdResult<u128> {
+    plus.checked_sub(minus)
+        .ok_or_else(|| StdError::generic_err("Cumulative index underflow"))
+}
+
+//Prefix sum of `plus` minus the prefix sum of `minus`
+pub fn net_prefix_sum<A: ReadonlyStorage, B: ReadonlyStorage>(plus: &A, minus: &B, slot: u64) -> StdResult<u128> {
+    net(prefix_sum(plus, slot), prefix_sum(minus, slot))
+}
+
+pub fn net_total<A: ReadonlyStorage, B: ReadonlyStorage>(plus: &A, minus: &B) -> StdResult<u128> {
+    net(total(plus), total(minus))
+}
+
+//Removes every node a slot's value is summed into. Clearing the path of every slot ever added to empties the tree.
+pub fn clear_slot<S: Storage>(storage: &mut S, slot: u64) -> StdResult<()> {
+    check_slot(slot)?;
+    let mut node = slot;
+    while node <= FENWICK_TREE_SIZE {
+        storage.remove(&node.to_be_bytes());
+        node += node & node.wrapping_neg();
+    }
+    Ok(())
+}
+
+//Smallest slot where the prefix sum of `plus` minus the prefix sum of `minus` reaches target.
+//Every slot of `minus` must hold at most the same slot of `plus`, so that every node difference is non-negative.
+pub fn find_net<A: ReadonlyStorage, B: ReadonlyStorage>(plus: &A, minus: &B, target: u128) -> StdResult<u64> {
+    if target == 0 || target > total(plus).saturating_sub(total(minus)) {
+        return Err(StdError::generic_err("Target is outside the cumulative index"));
+    }
+    let mut position = 0u64;
+    let mut remaining = target;
+    let mut step = FENWICK_TREE_SIZE;
+    while step > 0 {
+        let next = position + step;
+        if next <= FENWICK_TREE_SIZE {
+            let value = net(read_node(plus, next), read_node(minus, next))?;
+            if value < remaining {
+                position = next;
+                remaining -= value;
+            }
+        }
+        step >>= 1;
+    }
+    Ok(position + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    #[test]
+    fn test_prefix_sums_and_find() {
+        let mut storage = MockStorage::new();
+        let mut minus = MockStorage::new();
+        add(&mut storage, 1, 5).unwrap();
+        add(&mut storage, 2, 3).unwrap();
+        add(&mut storage, 7, 10).unwrap();
+        add(&mut storage, FENWICK_TREE_SIZE, 2).unwrap();
+        assert_eq!(total(&storage), 20);
+        assert_eq!(prefix_sum(&storage, 0), 0);
+        assert_eq!(prefix_sum(&storage, 2), 8);
+        assert_eq!(prefix_sum(&storage, 6), 8);
+        assert_eq!(prefix_sum(&storage, 7), 18);
+
+        assert_eq!(find_net(&storage, &minus, 1).unwrap(), 1);
+        assert_eq!(find_net(&storage, &minus, 5).unwrap(), 1);
+        assert_eq!(find_net(&storage, &minus, 6).unwrap(), 2);
+        assert_eq!(find_net(&storage, &minus, 9).unwrap(), 7);
+        assert_eq!(find_net(&storage, &minus, 19).unwrap(), FENWICK_TREE_SIZE);
+        assert!(find_net(&storage, &minus, 21).is_err());
+
+        sub(&mut storage, 2, 3).unwrap();
+        assert_eq!(find_net(&storage, &minus, 6).unwrap(), 7);
+        assert!(sub(&mut storage, 2, 1).is_err());
+        assert!(add(&mut storage, 0, 1).is_err());
+
+        //Slot 1 nets to 1 and slot 7 to 4
+        add(&mut minus, 1, 4).unwrap();
+        add(&mut minus, 7, 6).unwrap();
+        assert_eq!(find_net(&storage, &minus, 1).unwrap(), 1);
+        assert_eq!(find_net(&storage, &minus, 2).unwrap(), 7);
+        assert_eq!(find_net(&storage, &minus, 5).unwrap(), 7);
+        assert_eq!(find_net(&storage, &minus, 6).unwrap(), FENWICK_TREE_SIZE);
+        assert!(find_net(&storage, &minus, 8).is_err());
+        assert_eq!(net_prefix_sum(&storage, &minus, 7).unwrap(), 5);
+        assert_eq!(net_total(&storage, &minus).unwrap(), 7);
+
+        //A minus tree ahead of its plus tree is an error, not a wrapped around weight
+        add(&mut minus, 2, 2).unwrap();
+        assert!(net_prefix_sum(&storage, &minus, 2).is_err());
+        assert!(find_net(&storage, &minus, 2).is_err());
+
+        for slot in &[1, 2, 7] {
+            clear_slot(&mut minus, *slot).unwrap();
+        }
+        assert_eq!(total(&minus), 0);
+        assert_eq!(prefix_sum(&minus, 7), 0);
+    }
+}
@@ -4,6 +4,7 @@ pub mod state;
 pub mod viewing_keys;
 pub mod utils;
 mod rand;
+mod fenwick;
 mod constants;
 
 
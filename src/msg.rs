@@ -1,6 +1,6 @@
-use crate::state::{SecretContract};
+use crate::state::{SecretContract, RoundRecord, WeightCurve, UnbondingRecord, PendingConfigChanges, FeeSchedule, Role, PendingHandover, QueuedAction, Multisig, Proposal, ContractStatus, StakingMigration};
 use crate::viewing_keys::ViewingKey;
-use cosmwasm_std::{Binary, HumanAddr, Uint128};
+use cosmwasm_std::{Binary, HumanAddr, StdError, StdResult, Uint128};
 use serde::{Deserialize, Serialize};
 use secret_toolkit::utils::Query;
 use schemars::{JsonSchema};
@@ -16,7 +16,37 @@ pub struct InitMsg {
     pub staking_contract: SecretContract,
     pub viewing_key: String,
     pub prng_seed: Binary,
+    //Triggerer fee in basis points, paid to its recipient. The recipient defaults to the triggerer.
     pub triggerer_share_percentage: u64,
+    pub triggerer_fee_recipient: Option<HumanAddr>,
+    //No protocol or charity fee by default. The treasury defaults to the admin.
+    pub protocol_fee_bps: Option<u64>,
+    pub treasury: Option<HumanAddr>,
+    pub charity_fee_bps: Option<u64>,
+    pub charity: Option<HumanAddr>,
+    pub prize_tiers: Option<Vec<u64>>,
+    pub draw_reveal_delay: Option<u64>,
+    //Enables ticket mode. Amounts below a full ticket are not entered, and tickets carry no time weighting.
+    pub ticket_size: Option<Uint128>,
+    //Defaults to linear. Has no effect in ticket mode.
+    pub weight_curve: Option<WeightCurve>,
+    //Seconds a TriggerWithdraw waits before it can be withdrawn, defaults to 0
+    pub unbonding_period: Option<u64>,
+    //Both default to 0, no fee
+    pub early_exit_fee_percentage: Option<u64>,
+    pub early_exit_window: Option<u64>,
+    //min_deposit defaults to 1 sefi, no caps by default
+    pub min_deposit: Option<Uint128>,
+    pub max_deposit_per_user: Option<Uint128>,
+    pub pool_cap: Option<Uint128>,
+    //Round duration in seconds, defaults to a day
+    pub duration: Option<u64>,
+    //Any time on the schedule, e.g. a day at 16:00 UTC with a one day duration. Rounds then end on the schedule.
+    pub schedule_anchor: Option<u64>,
+    //Seconds timelocked admin actions wait before they can be executed, defaults to a day
+    pub timelock_delay: Option<u64>,
+    //No multisig by default
+    pub multisig: Option<Multisig>,
 }
 
 
@@ -52,31 +82,108 @@ pub enum HandleMsg {
     },
 
     //Triggerer
-    ClaimRewards {},
+    // Draw => 1.CommitDraw with sha256(secret) 2.RevealDraw with the secret at least `draw_reveal_delay` blocks later
+    CommitDraw {
+        entropy_hash: Binary,
+    },
+    RevealDraw {
+        entropy: Binary,
+    },
+
+    //Fee recipients, pays every fee balance owed to the sender. TriggeringCostWithdraw is kept for the triggerer fee recipient.
+    WithdrawFees {},
+
+    //Timelocked => 1.WithdrawExcess, EmergencyRedeemFromStaking, ChangeStakingContract or ChangeTimelockDelay queue the action.
+    //EmergencyRedeemFromStaking is not queued on a stopped contract, it runs at once.
+    //2.ExecuteAction once the delay has passed, by an address that may send the action itself
+    ExecuteAction {
+        id: u64,
+    },
+    CancelAction {
+        id: u64,
+    },
+    ChangeTimelockDelay {
+        delay: u64,
+    },
+
+    //Multisig => admin actions become proposals, they run when the threshold'th signer approves
+    Approve {
+        proposal_id: u64,
+    },
+    CancelProposal {
+        proposal_id: u64,
+    },
+    SetMultisig {
+        multisig: Option<Multisig>,
+    },
 
     //Admin
     TriggeringCostWithdraw {},
     WithdrawExcess{},
-    ChangeAdmin {
-        admin: HumanAddr
+    //Handover => 1.ProposeAdmin by an owner 2.AcceptAdmin by the proposed address, which then replaces every owner
+    ProposeAdmin {
+        address: HumanAddr,
     },
-    ChangeTriggerer {
-        admin: HumanAddr
+    AcceptAdmin {},
+    CancelAdminProposal {},
+    ProposeTriggerer {
+        address: HumanAddr,
+    },
+    AcceptTriggerer {},
+    CancelTriggererProposal {},
+    GrantRole {
+        role: Role,
+        address: HumanAddr,
+    },
+    RevokeRole {
+        role: Role,
+        address: HumanAddr,
     },
     ChangeTriggererShare {
         percentage: u64,
     },
+    ChangePrizeTiers {
+        tiers: Vec<u64>,
+    },
+    ChangeFeeSchedule {
+        fees: FeeSchedule,
+    },
+    ChangePermissionlessDraws {
+        enabled: bool,
+        max_bounty: Uint128,
+    },
+    ChangeWeightCurve {
+        curve: WeightCurve,
+    },
+    ChangeUnbondingPeriod {
+        period: u64,
+    },
+    ChangeEarlyExitFee {
+        percentage: u64,
+        window: u64,
+    },
+    ChangeDepositLimits {
+        min_deposit: Uint128,
+        max_deposit_per_user: Option<Uint128>,
+        pool_cap: Option<Uint128>,
+    },
 
     ChangeLotteryDuration {
         duration: u64
     },
-    StopContract {},
-    AllowWithdrawWhenStopped {},
-    ResumeContract {},
+    SetContractStatus {
+        status: ContractStatus,
+        reason: Option<String>,
+    },
 
 
     //Admin--> Changing contract
-    // ChangeStakingContractFlow => 1. stopContact 2.EmergencyRedeemFromStaking 4.ChangeStakingContract 5.RedelegateToNewContract 6.ResumeContract
+    //Timelocked, redeems everything from the staking contract and stakes it in new_contract in one transaction
+    MigrateStakingContract {
+        new_contract: SecretContract,
+    },
+    //Manual alternative to MigrateStakingContract. EmergencyRedeemFromStaking is not timelocked, the contract is already stopped.
+    // ChangeStakingContractFlow => 1. SetContractStatus FullyStopped 2.EmergencyRedeemFromStaking 4.ChangeStakingContract 5.RedelegateToNewContract 6.SetContractStatus Normal
     EmergencyRedeemFromStaking {},
     ChangeStakingContract {
         address: HumanAddr,
@@ -96,28 +203,49 @@ pub enum HandleAnswer {
     Redeem { status: LPStakingResponseStatus },
     CreateViewingKey { key: ViewingKey },
     SetViewingKey { status: ResponseStatus },
-    StopContract { status: ResponseStatus },
-    AllowWithdrawWhenStopped { status: ResponseStatus },
-    ResumeContract { status: ResponseStatus },
-    ChangeAdmin { status: ResponseStatus },
-    ChangeTriggerer { status: ResponseStatus },
+    SetContractStatus { status: ResponseStatus },
+    ProposeAdmin { status: ResponseStatus },
+    AcceptAdmin { status: ResponseStatus },
+    CancelAdminProposal { status: ResponseStatus },
+    ProposeTriggerer { status: ResponseStatus },
+    AcceptTriggerer { status: ResponseStatus },
+    CancelTriggererProposal { status: ResponseStatus },
+    GrantRole { status: ResponseStatus },
+    RevokeRole { status: ResponseStatus },
     ChangeTriggererShare { status: ResponseStatus },
+    ChangePrizeTiers { status: ResponseStatus },
+    ChangeFeeSchedule { status: ResponseStatus },
+    ChangePermissionlessDraws { status: ResponseStatus },
+    ChangeWeightCurve { status: ResponseStatus },
+    ChangeUnbondingPeriod { status: ResponseStatus },
+    ChangeEarlyExitFee { status: ResponseStatus },
+    ChangeDepositLimits { status: ResponseStatus },
 
     ChangeStakingContract { status: ResponseStatus },
+    MigrateStakingContract { status: ResponseStatus, amount: Uint128 },
     ChangeLotteryDuration {
         status: ResponseStatus,
     },
 
     TriggeringCostWithdraw { status: ResponseStatus },
+    WithdrawFees { status: ResponseStatus, amount: Uint128 },
     WithdrawExcess { status: ResponseStatus },
+    QueueAction { status: ResponseStatus, id: u64, eta: u64 },
+    CancelAction { status: ResponseStatus },
+    ChangeTimelockDelay { status: ResponseStatus },
+    Propose { status: ResponseStatus, proposal_id: u64 },
+    Approve { status: ResponseStatus, approvals: u64 },
+    CancelProposal { status: ResponseStatus },
+    SetMultisig { status: ResponseStatus },
 
 
-    ClaimRewards { status: ResponseStatus, winner: HumanAddr },
+    CommitDraw { status: ResponseStatus, reveal_height: u64 },
+    ClaimRewards { status: ResponseStatus, winners: Vec<(HumanAddr, Uint128)>, rolled_over: bool },
     EmergencyRedeemFromStaking { status: ResponseStatus },
     Deposit { status: ResponseStatus },
     Redelegate { status: ResponseStatus },
     RedelegateToContract { status: ResponseStatus },
-    TriggerWithdraw { status: ResponseStatus },
+    TriggerWithdraw { status: ResponseStatus, early_exit_fee: Uint128 },
     Withdraw { status: ResponseStatus },
 
     //Tests
@@ -144,6 +272,19 @@ pub enum QueryMsg {
     TokenInfo {},
     IncentivizedToken {},
     LotteryInfo {},
+    Jackpot {},
+    RoundInfo { round_id: u64 },
+    TicketInfo {},
+    PendingConfigChanges {},
+    Roles {},
+    QueuedActions {},
+    Proposals {},
+    ContractStatus {},
+    StakingMigrations {},
+    //Every parameter except the viewing key and the prng seed
+    Config {},
+    //Compares the actual token and staking balances with what the contract owes
+    Reconcile {},
 
     // Authenticated
     Rewards {
@@ -155,9 +296,11 @@ pub enum QueryMsg {
         address: HumanAddr,
         key: String,
     },
+    //What can be withdrawn at time, unbondings released by then included
     AvailableTokensForWithdrawl {
         address: HumanAddr,
         key: String,
+        time: u64,
     },
     UserPastRecords {
         address: HumanAddr,
@@ -167,6 +310,21 @@ pub enum QueryMsg {
         address: HumanAddr,
         key: String,
     },
+    Tickets {
+        address: HumanAddr,
+        key: String,
+    },
+    PendingUnbondings {
+        address: HumanAddr,
+        key: String,
+    },
+    //Fee a TriggerWithdraw of amount, or of everything delegated, would be charged at time
+    EarlyExitFee {
+        address: HumanAddr,
+        key: String,
+        amount: Option<Uint128>,
+        time: u64,
+    },
     PastRecords {},
     PastAllRecords {},
 
@@ -178,16 +336,19 @@ impl Query for QueryMsg {
 }
 
 impl QueryMsg {
-    pub fn get_validation_params(&self) -> (&HumanAddr, ViewingKey) {
-        match self {
+    pub fn get_validation_params(&self) -> StdResult<(&HumanAddr, ViewingKey)> {
+        Ok(match self {
             QueryMsg::Rewards { address, key, .. } => (address, ViewingKey(key.clone())),
             QueryMsg::Balance { address, key } => (address, ViewingKey(key.clone())),
-            QueryMsg::AvailableTokensForWithdrawl { address, key } => (address, ViewingKey(key.clone())),
+            QueryMsg::AvailableTokensForWithdrawl { address, key, .. } => (address, ViewingKey(key.clone())),
             QueryMsg::UserPastRecords { address, key } => (address, ViewingKey(key.clone())),
             QueryMsg::UserAllPastRecords { address, key } => (address, ViewingKey(key.clone())),
+            QueryMsg::Tickets { address, key } => (address, ViewingKey(key.clone())),
+            QueryMsg::PendingUnbondings { address, key } => (address, ViewingKey(key.clone())),
+            QueryMsg::EarlyExitFee { address, key, .. } => (address, ViewingKey(key.clone())),
 
-            _ => panic!("This should never happen"),
-        }
+            _ => return Err(StdError::generic_err("This query does not take a viewing key")),
+        })
     }
 }
 
@@ -213,7 +374,9 @@ pub enum QueryAnswer {
         amount: Uint128,
     },
     AvailableTokensForWithdrawl {
-        amount: Uint128
+        amount: Uint128,
+        //unbondings still waiting for their release time
+        pending_unbondings: Uint128,
     },
 
     RewardToken {
@@ -238,12 +401,58 @@ pub enum QueryAnswer {
     },
 
     LotteryInfo {
+        round_id: u64,
         start_time: u64,
         end_time: u64,
         duration: u64,
+        weight_curve: WeightCurve,
+        schedule_anchor: Option<u64>,
         is_stopped:bool,
         is_stopped_with_withdraw:bool,
     },
+    ContractStatus {
+        status: ContractStatus,
+        reason: Option<String>,
+    },
+    StakingMigrations {
+        migrations: Vec<StakingMigration>,
+    },
+    Config {
+        version: String,
+        admins: Vec<HumanAddr>,
+        triggerers: Vec<HumanAddr>,
+        pending_handover: PendingHandover,
+        fees: FeeSchedule,
+        prize_tiers: Vec<u64>,
+        token: SecretContract,
+        staking_contract: SecretContract,
+        own_addr: HumanAddr,
+        draw_reveal_delay: u64,
+        permissionless_draws: bool,
+        max_triggerer_bounty: Uint128,
+        ticket_size: Option<Uint128>,
+        weight_curve: WeightCurve,
+        unbonding_period: u64,
+        early_exit_fee_percentage: u64,
+        early_exit_window: u64,
+        min_deposit: Uint128,
+        max_deposit_per_user: Option<Uint128>,
+        pool_cap: Option<Uint128>,
+        timelock_delay: u64,
+        multisig: Option<Multisig>,
+        status: ContractStatus,
+        status_reason: Option<String>,
+    },
+    Reconcile {
+        token_balance: Uint128,
+        staked_balance: Uint128,
+        //delegated plus available for withdraw or unbonding
+        user_liabilities: Uint128,
+        fees: Uint128,
+        prize_pool: Uint128,
+        surplus: Uint128,
+        shortfall: Uint128,
+    },
 
     PastRecords {
         past_rewards: Vec<(u64, u64)>,
@@ -253,6 +462,58 @@ pub enum QueryAnswer {
         past_rewards: Vec<(u64, u64)>,
     },
 
+    Jackpot {
+        rolled_over_prize: Uint128,
+        rolled_over_rounds: u64,
+    },
+
+    RoundInfo {
+        round: RoundRecord,
+    },
+
+    TicketInfo {
+        ticket_size: Option<Uint128>,
+        total_tickets: Uint128,
+        holders: u64,
+    },
+
+    //The holder's tickets in the current round's draw, numbered first_ticket to last_ticket.
+    //The numbers are a snapshot and not fixed at purchase: they shift whenever a holder earlier in the pool
+    //buys or gives up tickets, so they can only be relied on once the draw is committed.
+    Tickets {
+        tickets: Uint128,
+        first_ticket: Option<Uint128>,
+        last_ticket: Option<Uint128>,
+    },
+
+    PendingUnbondings {
+        unbondings: Vec<UnbondingRecord>,
+    },
+
+    //The changes are applied when round_id is drawn, which can happen from draw_time on. A round that rolls over leaves them pending.
+    PendingConfigChanges {
+        changes: PendingConfigChanges,
+        round_id: u64,
+        draw_time: u64,
+    },
+    Roles {
+        roles: Vec<(Role, Vec<HumanAddr>)>,
+        pending: PendingHandover,
+    },
+    QueuedActions {
+        actions: Vec<QueuedAction>,
+        delay: u64,
+    },
+    Proposals {
+        proposals: Vec<Proposal>,
+        multisig: Option<Multisig>,
+    },
+
+    EarlyExitFee {
+        fee: Uint128,
+        //time from which withdrawing is free
+        fee_free_from: u64,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -283,6 +544,16 @@ pub struct RewardsInfo {
     pub rewards: Uint128,
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct LPStakingBalanceResponse {
+    pub balance: BalanceInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BalanceInfo {
+    pub amount: Uint128,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum LPStakingQueryMsg {
@@ -290,7 +561,11 @@ pub enum LPStakingQueryMsg {
         address: HumanAddr,
         key: String,
         height: u64,
-    }
+    },
+    Balance {
+        address: HumanAddr,
+        key: String,
+    },
 }
 
 impl Query for LPStakingQueryMsg {
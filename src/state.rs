@@ -1,22 +1,176 @@
-use cosmwasm_std::{HumanAddr, Uint128};
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use secret_toolkit::incubator::generational_store::Index;
 
 #[derive(Serialize, Deserialize,  PartialEq, Debug, Clone)]
 pub struct Config {
-    pub admin: HumanAddr,
-    pub triggerer: HumanAddr,
-    pub triggerer_share_percentage: u64,
+    pub fees: FeeSchedule,
+    pub prize_tiers: Vec<u64>,
     pub token: SecretContract,
     pub staking_contract: SecretContract,
     pub viewing_key: String,
     pub prng_seed: Vec<u8>,
-    pub is_stopped: bool,
-    pub is_stopped_can_withdraw:bool,
+    pub draw_reveal_delay: u64,
+    //anyone may trigger a draw and is paid the triggerer share, capped, in the same transaction
+    pub permissionless_draws: bool,
+    pub max_triggerer_bounty: Uint128,
+    //Ticket mode, fixed at init: every full ticket_size delegated is one ticket and the draw picks a ticket number
+    pub ticket_size: Option<Uint128>,
+    //The current round's curve is kept by the lottery, changes to this one are staged until the round is drawn
+    pub weight_curve: WeightCurve,
+    //Seconds between TriggerWithdraw and the tokens becoming withdrawable, 0 releases them at once
+    pub unbonding_period: u64,
+    //Charged on the part of a TriggerWithdraw taken from deposits made less than early_exit_window seconds ago.
+    //In basis points like the fee schedule, 10000 is the whole amount.
+    pub early_exit_fee_percentage: u64,
+    pub early_exit_window: u64,
+    //Deposit limits, in usefi. A deposit that breaks one fails and the Send that carried it is reverted.
+    pub min_deposit: Uint128,
+    pub max_deposit_per_user: Option<Uint128>,
+    pub pool_cap: Option<Uint128>,
+    //Seconds a timelocked action waits in the queue before it can be executed
+    pub timelock_delay: u64,
+    //When set, admin actions become proposals that run once threshold signers approved them
+    pub multisig: Option<Multisig>,
+    pub status: ContractStatus,
+    //why the admin set the status, shown to users
+    pub status_reason: Option<String>,
     pub own_addr: HumanAddr,
 }
 
+//Fees taken from every prize, in basis points. The triggerer fee goes to its recipient,
+//or to whoever triggers the draw when draws are permissionless.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeSchedule {
+    pub triggerer_fee_bps: u64,
+    pub triggerer_fee_recipient: HumanAddr,
+    pub protocol_fee_bps: u64,
+    pub treasury: HumanAddr,
+    pub charity_fee_bps: u64,
+    pub charity: Option<HumanAddr>,
+}
+
+//Admin commands are split between roles, each held by a set of addresses
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    //grants and revokes roles, changes the fee schedule and the staking contract
+    Owner,
+    //stops the contract, resuming it is left to the owner
+    Pauser,
+    //changes the lottery parameters
+    ParameterManager,
+    //withdraws excess rewards
+    FeeCollector,
+    //commits and reveals draws, collects the triggerer fee
+    Triggerer,
+}
+
+//Admin and triggerer handovers waiting for the proposed address to accept them
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PendingHandover {
+    pub admin: Option<HumanAddr>,
+    pub triggerer: Option<HumanAddr>,
+}
+
+//Admin operations that move funds or the staking contract, they are queued and executed after the timelock delay
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelockedAction {
+    ChangeStakingContract { address: HumanAddr, contract_hash: String },
+    WithdrawExcess {},
+    EmergencyRedeemFromStaking {},
+    //A shorter delay would let the other actions skip the queue, so the delay is timelocked as well
+    ChangeTimelockDelay { delay: u64 },
+    MigrateStakingContract { new_contract: SecretContract },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueuedAction {
+    pub id: u64,
+    pub action: TimelockedAction,
+    pub queued_by: HumanAddr,
+    //earliest block time the action can be executed at
+    pub eta: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ActionQueue {
+    pub next_id: u64,
+    pub actions: Vec<QueuedAction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Multisig {
+    pub signers: Vec<HumanAddr>,
+    pub threshold: u64,
+}
+
+//Admin actions that need the multisig's approval when there is one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    //queued behind the timelock once approved
+    Queue { action: TimelockedAction },
+    ProposeAdmin { address: HumanAddr },
+    ProposeTriggerer { address: HumanAddr },
+    GrantRole { role: Role, address: HumanAddr },
+    RevokeRole { role: Role, address: HumanAddr },
+    SetMultisig { multisig: Option<Multisig> },
+    CancelAction { id: u64 },
+    ChangeFeeSchedule { fees: FeeSchedule },
+    //only resuming, a Pauser makes the status more restrictive without approvals
+    SetContractStatus { status: ContractStatus, reason: Option<String> },
+    ChangeTriggererShare { percentage: u64 },
+    ChangePrizeTiers { tiers: Vec<u64> },
+    ChangePermissionlessDraws { enabled: bool, max_bounty: Uint128 },
+    ChangeWeightCurve { curve: WeightCurve },
+    ChangeUnbondingPeriod { period: u64 },
+    ChangeEarlyExitFee { percentage: u64, window: u64 },
+    ChangeDepositLimits { min_deposit: Uint128, max_deposit_per_user: Option<Uint128>, pool_cap: Option<Uint128> },
+    ChangeLotteryDuration { duration: u64 },
+}
+
+//Runs on behalf of the proposer, who needs the action's role both when proposing and when it runs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Proposal {
+    pub id: u64,
+    pub action: AdminAction,
+    pub proposer: HumanAddr,
+    pub approvals: Vec<HumanAddr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ProposalBook {
+    pub next_id: u64,
+    pub proposals: Vec<Proposal>,
+}
+
+//Which messages the contract accepts, see check_contract_status
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Normal,
+    //no new deposits
+    DepositsPaused,
+    //no draw can be committed or revealed
+    DrawsPaused,
+    //stopped, users can withdraw everything they delegated
+    WithdrawOnly,
+    //stopped, only the ChangeStakingContractFlow and admin messages
+    FullyStopped,
+}
+
+//Appended by every MigrateStakingContract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingMigration {
+    pub old_contract: HumanAddr,
+    pub new_contract: HumanAddr,
+    //principal and restaked rewards moved
+    pub amount: Uint128,
+    pub time: u64,
+}
+
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, JsonSchema)]
 pub struct SecretContract {
     pub address: HumanAddr,
@@ -32,9 +186,119 @@ pub struct Lottery {
     pub duration: u64,
     pub start_time: u64,
     pub end_time: u64,
+    pub round_id: u64,
+    pub weight_curve: WeightCurve,
+    //Rounds end on anchor + k * duration whenever the draw is triggered, instead of duration after the draw
+    pub schedule_anchor: Option<u64>,
 }
 
-//Append store
+//How deposits made during a round count in its draw. Deposits carried over from earlier rounds always count in full.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightCurve {
+    //in proportion to the time left in the round
+    Linear,
+    //in full if made in the first cutoff_percentage of the round, otherwise not at all
+    Step { cutoff_percentage: u64 },
+    //in full, whenever made before the round ended
+    Flat,
+    //like Linear, then the square root of every user's total weight, so that large holders count for less
+    SquareRoot,
+}
+
+//Written once when a round is drawn, keyed by round_id
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoundRecord {
+    pub round_id: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub duration: u64,
+    pub total_weight: Uint128,
+    pub participants: u64,
+    pub entropy_hash: Binary,
+    pub winners: Vec<(HumanAddr, Uint128)>,
+    pub prize: Uint128,
+    pub triggerer_fee: Uint128,
+    pub status: RoundStatus,
+    pub weight_curve: WeightCurve,
+    //Ticket mode only, in the same order as winners
+    pub winning_tickets: Vec<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundStatus {
+    Drawn,
+    RolledOver,
+}
+
+//Every depositor gets a slot in the draw index on their first deposit, holders counts slots with a non-zero entry.
+//In ticket mode tickets are numbered in slot order, so a holder's numbers shift down when someone in an earlier slot leaves.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SlotRegistry {
+    pub next_slot: u64,
+    pub holders: u64,
+}
+
+//A user's deposits of the round round_id that are still in the pool, and the part of them that
+//does not count in that round's draw because it was deposited after the round started.
+//In ticket mode both are in tickets, and only tickets bought after the round ended fall short.
+//An entry of an earlier round is stale and counts as carried over.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
+pub struct RoundEntry {
+    pub round_id: u64,
+    pub amount: Uint128,
+    pub deficit: Uint128,
+}
+
+//Admin changes to the round parameters, applied together when the current round is drawn
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, JsonSchema)]
+pub struct PendingConfigChanges {
+    pub duration: Option<u64>,
+    pub fees: Option<FeeSchedule>,
+    pub prize_tiers: Option<Vec<u64>>,
+    pub weight_curve: Option<WeightCurve>,
+    pub unbonding_period: Option<u64>,
+    pub early_exit_fee: Option<EarlyExitFee>,
+    pub deposit_limits: Option<DepositLimits>,
+    pub permissionless_draws: Option<PermissionlessDraws>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyExitFee {
+    pub percentage: u64,
+    pub window: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositLimits {
+    pub min_deposit: Uint128,
+    pub max_deposit_per_user: Option<Uint128>,
+    pub pool_cap: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermissionlessDraws {
+    pub enabled: bool,
+    pub max_bounty: Uint128,
+}
+
+//Fixed by CommitDraw, consumed by RevealDraw
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DrawCommitment {
+    pub entropy_hash: Vec<u8>,
+    pub committer: HumanAddr,
+    pub commit_height: u64,
+    pub commit_time: u64,
+    pub round_id: u64,
+}
+
+//Addresses that let a commitment for the round lapse, they can not commit again until it is drawn
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct LapsedCommitters {
+    pub round_id: u64,
+    pub committers: Vec<HumanAddr>,
+}
 
 //Append store
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -42,6 +306,10 @@ pub struct LastLotteryResults {
     //winning amount and time
     pub winning_amount:u64, //Append store
     pub time:u64,
+    //every winner of the draw with the amount credited, ordered by prize tier
+    pub winners: Vec<(HumanAddr, Uint128)>,
+    //no eligible entries, the prize stays in the pool for the next round
+    pub rolled_over: bool,
 }
 
 #[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
@@ -49,16 +317,52 @@ pub struct SupplyPool {
     pub total_tokens_staked: Uint128,
     pub total_rewards_restaked:Uint128,
     pub pending_staking_rewards:Uint128,
-    pub triggering_cost:Uint128
+    //fees owed to their recipients until withdrawn: the triggerer fee recipient, the treasury and the charity
+    pub triggering_cost:Uint128,
+    pub protocol_fees:Uint128,
+    pub charity_fees:Uint128,
+    //part of total_rewards_restaked + pending_staking_rewards carried over from rounds without winners
+    pub rolled_over_prize:Uint128,
+    pub rolled_over_rounds:u64,
+    //part of total_rewards_restaked collected as early exit fees, earmarked for the next draw
+    pub early_exit_fees:Uint128,
+    //held by the contract for users, available for withdraw or unbonding
+    pub total_pending_withdrawals:Uint128,
 }
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+//Running sums over every UserInfo, kept apart from SupplyPool to check its totals after each handle
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
+pub struct UserTotals {
+    //sum of amount_delegated, expected to match SupplyPool.total_tokens_staked
+    pub principal: Uint128,
+    //sum of available_tokens_for_withdraw and unbondings, expected to match SupplyPool.total_pending_withdrawals
+    pub pending_withdrawals: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
 pub struct UserInfo {
     pub amount_delegated: Uint128,
     pub available_tokens_for_withdraw:Uint128,
     pub total_won:Uint128,
-    pub entries: Vec<( Uint128,u64)>,
-    pub entry_index:Vec<Index>,
+    //amount_delegated is at most two entries: this round's, and the rest carried over from earlier rounds at full weight
+    pub round_entry: RoundEntry,
+    //TriggerWithdraw amounts still unbonding, oldest first. Withdraw moves matured ones to available_tokens_for_withdraw
+    pub unbondings: Vec<UnbondingRecord>,
+    pub recent_deposit: RecentDeposit,
+}
+
+//Deposits made inside the early exit window, merged. entry_time is their amount-weighted deposit time.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Default)]
+pub struct RecentDeposit {
+    pub amount: Uint128,
+    pub entry_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, JsonSchema)]
+pub struct UnbondingRecord {
+    pub amount: Uint128,
+    pub start_time: u64,
+    pub release_time: u64,
 }
 
 
@@ -69,13 +373,6 @@ pub struct UserWinningHistory{
     pub time:u64,
 }
 
-//Testing
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct LotteryEntries{
-    pub user_address: HumanAddr,
-    pub amount:Uint128,
-    pub entry_time:u64,
-}
 
 
 
@@ -11,5 +11,21 @@ pub fn create_hashed_password(s1: &str) -> [u8; VIEWING_KEY_SIZE] {
     sha_256(s1.as_bytes())
 }
 
+// Integer square root, rounded down
+pub fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    // Newton's method from a power of two at or above the root, decreasing until it settles
+    let mut root = 1u128 << ((128 - value.leading_zeros() + 1) / 2);
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
 
 